@@ -0,0 +1,126 @@
+#[cfg(not(feature = "library"))]
+use cosmwasm_std::entry_point;
+use cosmwasm_std::{
+    to_binary, Addr, Binary, Deps, DepsMut, Empty, Env, MessageInfo, Order, Response, StdResult,
+};
+use cw2::set_contract_version;
+use cw_storage_plus::Bound;
+
+use crate::error::ContractError;
+use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ServiceQueryMsg};
+use crate::state::{ADMIN, CALLERS, RATE_LIMIT};
+
+const CONTRACT_NAME: &str = "crates.io:cw-rate-limiter-service";
+const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn instantiate(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    msg: InstantiateMsg,
+) -> Result<Response, ContractError> {
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    if msg.rate_limit.is_zero() {
+        return Err(ContractError::ZeroRate {});
+    }
+    ADMIN.save(
+        deps.storage,
+        &msg.admin
+            .map(|a| deps.api.addr_validate(&a))
+            .transpose()?
+            .unwrap_or(info.sender),
+    )?;
+    for caller in msg.callers {
+        CALLERS.save(deps.storage, &deps.api.addr_validate(&caller)?, &Empty {})?;
+    }
+    RATE_LIMIT.init(deps.storage, &msg.rate_limit)?;
+    Ok(Response::default().add_attribute("method", "instantiate"))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn execute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ExecuteMsg,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::Consume { key, cost } => execute_consume(deps, env, info, key, cost),
+        ExecuteMsg::UpdateCallers { add, remove } => {
+            execute_update_callers(deps, info, add, remove)
+        }
+    }
+}
+
+pub fn execute_consume(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    key: String,
+    cost: u64,
+) -> Result<Response, ContractError> {
+    if !CALLERS.has(deps.storage, &info.sender) {
+        return Err(ContractError::NotCaller {});
+    }
+    RATE_LIMIT.limit_many(deps.storage, &env, &[(&key, cost)])?;
+    Ok(Response::default()
+        .add_attribute("method", "consume")
+        .add_attribute("caller", info.sender)
+        .add_attribute("key", key)
+        .add_attribute("cost", cost.to_string()))
+}
+
+pub fn execute_update_callers(
+    deps: DepsMut,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    if ADMIN.load(deps.storage)? != info.sender {
+        return Err(ContractError::Unauthorized {});
+    }
+    for caller in add {
+        CALLERS.save(deps.storage, &deps.api.addr_validate(&caller)?, &Empty {})?;
+    }
+    for caller in remove {
+        CALLERS.remove(deps.storage, &deps.api.addr_validate(&caller)?);
+    }
+    Ok(Response::default()
+        .add_attribute("method", "update_callers")
+        .add_attribute("sender", info.sender))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Service(msg) => query_service(deps, msg),
+        QueryMsg::RateLimiter(msg) => RATE_LIMIT.handle_query(deps.storage, &env, msg),
+    }
+}
+
+fn query_service(deps: Deps, msg: ServiceQueryMsg) -> StdResult<Binary> {
+    match msg {
+        ServiceQueryMsg::Admin {} => to_binary(&ADMIN.load(deps.storage)?),
+        ServiceQueryMsg::Callers { start_after, limit } => {
+            let start_after = start_after.map(Addr::unchecked);
+            to_binary(
+                &CALLERS
+                    .keys(
+                        deps.storage,
+                        start_after.as_ref().map(Bound::exclusive),
+                        None,
+                        Order::Ascending,
+                    )
+                    .take(limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize)
+                    .collect::<StdResult<Vec<Addr>>>()?,
+            )
+        }
+        ServiceQueryMsg::IsCaller { address } => {
+            to_binary(&CALLERS.has(deps.storage, &Addr::unchecked(address)))
+        }
+    }
+}
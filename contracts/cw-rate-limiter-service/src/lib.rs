@@ -0,0 +1,9 @@
+pub mod contract;
+pub mod error;
+pub mod msg;
+pub mod state;
+
+#[cfg(test)]
+mod tests;
+
+pub use cw_rate_limiter::{Rate, RateLimitError};
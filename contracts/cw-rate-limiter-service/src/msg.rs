@@ -0,0 +1,55 @@
+use cosmwasm_schema::{cw_serde, QueryResponses};
+
+use cw_rate_limiter::{Rate, RateLimiterQueryMsg};
+
+#[cw_serde]
+pub struct InstantiateMsg {
+    pub rate_limit: Rate,
+    /// The address that may perform administrative actions. Defaults
+    /// to the instantiator.
+    pub admin: Option<String>,
+    /// Contracts that may consume quota.
+    pub callers: Vec<String>,
+}
+
+#[cw_serde]
+pub enum ExecuteMsg {
+    /// Charges COST units to KEY, failing if it does not have enough
+    /// quota left. Keys are shared between callers, so every caller
+    /// that consumes from a key draws on the same budget. Only
+    /// callable by allowlisted callers.
+    Consume { key: String, cost: u64 },
+    /// Adds and removes allowlisted callers. Only callable by the
+    /// admin.
+    UpdateCallers {
+        add: Vec<String>,
+        remove: Vec<String>,
+    },
+}
+
+#[cw_serde]
+#[derive(QueryResponses)]
+#[serde(untagged)]
+#[query_responses(nested)]
+pub enum QueryMsg {
+    Service(ServiceQueryMsg),
+    RateLimiter(RateLimiterQueryMsg),
+}
+
+#[cw_serde]
+#[derive(QueryResponses)]
+pub enum ServiceQueryMsg {
+    #[returns(String)]
+    Admin {},
+
+    /// Lists allowlisted callers in ascending order.
+    #[returns(Vec<String>)]
+    Callers {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    /// Returns true if an address may consume quota.
+    #[returns(bool)]
+    IsCaller { address: String },
+}
@@ -0,0 +1,11 @@
+use cosmwasm_std::{Addr, Empty};
+use cw_storage_plus::{Item, Map};
+
+use cw_rate_limiter::RateLimiter;
+
+pub const RATE_LIMIT: RateLimiter = RateLimiter::new("rate_limit", "keys")
+    .with_stats("key_stats", "total_stats")
+    .with_throttled_index("throttled");
+pub const ADMIN: Item<Addr> = Item::new("admin");
+/// Contracts that may consume quota.
+pub const CALLERS: Map<&Addr, Empty> = Map::new("callers");
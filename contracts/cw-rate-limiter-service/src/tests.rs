@@ -0,0 +1,184 @@
+use cosmwasm_std::{Addr, Empty};
+use cw_multi_test::{next_block, App, Contract, ContractWrapper, Executor};
+use cw_rate_limiter::{KeyState, Rate, RateLimitError, RateLimiterQueryMsg};
+
+use crate::{
+    error::ContractError,
+    msg::{ExecuteMsg, InstantiateMsg, QueryMsg, ServiceQueryMsg},
+};
+
+fn rate_limiter_service() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(
+        crate::contract::execute,
+        crate::contract::instantiate,
+        crate::contract::query,
+    );
+    Box::new(contract)
+}
+
+struct Test {
+    app: App,
+    admin: Addr,
+    service: Addr,
+}
+
+impl Test {
+    fn new(rate: Rate, callers: &[&str]) -> Self {
+        let mut app = App::default();
+        let admin = Addr::unchecked("admin");
+        let code_id = app.store_code(rate_limiter_service());
+        let service = app
+            .instantiate_contract(
+                code_id,
+                admin.clone(),
+                &InstantiateMsg {
+                    rate_limit: rate,
+                    admin: None,
+                    callers: callers.iter().map(|c| c.to_string()).collect(),
+                },
+                &[],
+                "rate limiter service",
+                None,
+            )
+            .unwrap();
+        Self {
+            app,
+            admin,
+            service,
+        }
+    }
+
+    fn consume(&mut self, caller: &str, key: &str, cost: u64) -> Result<(), ContractError> {
+        self.app
+            .execute_contract(
+                Addr::unchecked(caller),
+                self.service.clone(),
+                &ExecuteMsg::Consume {
+                    key: key.to_string(),
+                    cost,
+                },
+                &[],
+            )
+            .map(|_| ())
+            .map_err(|e| e.downcast().unwrap())
+    }
+}
+
+#[test]
+fn test_shared_budget() {
+    let mut test = Test::new(Rate::PerBlock(3), &["proxy_a", "proxy_b"]);
+
+    test.consume("proxy_a", "collection", 2).unwrap();
+    test.consume("proxy_b", "collection", 1).unwrap();
+    assert_eq!(
+        test.consume("proxy_a", "collection", 1).unwrap_err(),
+        ContractError::Rate(RateLimitError::Limited {
+            key: "collection".to_string(),
+            blocks_remaining: 1
+        })
+    );
+    test.consume("proxy_b", "other", 3).unwrap();
+
+    let state: KeyState = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.service,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::KeyState {
+                key: "collection".to_string(),
+            }),
+        )
+        .unwrap();
+    assert_eq!(state.usage.remaining, 0);
+
+    test.app.update_block(next_block);
+    test.consume("proxy_b", "collection", 3).unwrap();
+}
+
+#[test]
+fn test_callers() {
+    let mut test = Test::new(Rate::PerBlock(3), &["proxy_a"]);
+    assert_eq!(
+        test.consume("proxy_b", "collection", 1).unwrap_err(),
+        ContractError::NotCaller {}
+    );
+
+    let update = ExecuteMsg::UpdateCallers {
+        add: vec!["proxy_b".to_string()],
+        remove: vec!["proxy_a".to_string()],
+    };
+    let err: ContractError = test
+        .app
+        .execute_contract(
+            Addr::unchecked("proxy_a"),
+            test.service.clone(),
+            &update,
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+    test.app
+        .execute_contract(test.admin.clone(), test.service.clone(), &update, &[])
+        .unwrap();
+
+    test.consume("proxy_b", "collection", 1).unwrap();
+    assert_eq!(
+        test.consume("proxy_a", "collection", 1).unwrap_err(),
+        ContractError::NotCaller {}
+    );
+
+    let callers: Vec<String> = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.service,
+            &QueryMsg::Service(ServiceQueryMsg::Callers {
+                start_after: None,
+                limit: None,
+            }),
+        )
+        .unwrap();
+    assert_eq!(callers, vec!["proxy_b".to_string()]);
+    let is_caller: bool = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.service,
+            &ServiceQueryMsg::IsCaller {
+                address: "proxy_a".to_string(),
+            },
+        )
+        .unwrap();
+    assert!(!is_caller);
+    let admin: String = test
+        .app
+        .wrap()
+        .query_wasm_smart(&test.service, &ServiceQueryMsg::Admin {})
+        .unwrap();
+    assert_eq!(admin, "admin");
+}
+
+#[test]
+fn test_zero_rate() {
+    let mut app = App::default();
+    let code_id = app.store_code(rate_limiter_service());
+    let err: ContractError = app
+        .instantiate_contract(
+            code_id,
+            Addr::unchecked("admin"),
+            &InstantiateMsg {
+                rate_limit: Rate::PerBlock(0),
+                admin: None,
+                callers: vec![],
+            },
+            &[],
+            "rate limiter service",
+            None,
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::ZeroRate {});
+}
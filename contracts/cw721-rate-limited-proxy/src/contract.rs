@@ -1,20 +1,27 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, WasmMsg,
+    to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, WasmMsg,
 };
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
 use cw721_proxy::ProxyExecuteMsg;
+use cw_utils::Expiration;
 
-use cw_rate_limiter::Rate;
+use cw_rate_limiter::{
+    ExportedKey, Rate, RateLimiterConfig, RateTransition, SignedVoucher, VoucherAuthority,
+};
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{ORIGIN, RATE_LIMIT};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, ProxyQueryMsg, QueryMsg};
+use crate::state::{
+    PauseState, PendingAdmin, ADMIN, ORIGIN, PAUSED, PAUSER, PENDING_ADMIN, RATE_LIMIT,
+};
 
 const CONTRACT_NAME: &str = "crates.io:cw721-proxy-rate-limit";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
+const DEFAULT_MIGRATION_BATCH: u32 = 100;
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
@@ -26,6 +33,13 @@ pub fn instantiate(
     ORIGIN.save(
         deps.storage,
         &msg.origin
+            .map(|a| deps.api.addr_validate(&a))
+            .transpose()?
+            .unwrap_or_else(|| info.sender.clone()),
+    )?;
+    ADMIN.save(
+        deps.storage,
+        &msg.admin
             .map(|a| deps.api.addr_validate(&a))
             .transpose()?
             .unwrap_or(info.sender),
@@ -33,10 +47,7 @@ pub fn instantiate(
     if msg.rate_limit.is_zero() {
         Err(ContractError::ZeroRate {})
     } else {
-        let (rate, units) = match msg.rate_limit {
-            Rate::PerBlock(rate) => (rate, "nfts_per_block"),
-            Rate::Blocks(rate) => (rate, "blocks_per_nft"),
-        };
+        let (rate, units) = rate_attributes(msg.rate_limit);
         RATE_LIMIT.init(deps.storage, &msg.rate_limit)?;
         Ok(Response::default()
             .add_attribute("method", "instantiate")
@@ -54,6 +65,23 @@ pub fn execute(
 ) -> Result<Response, ContractError> {
     match msg {
         ExecuteMsg::ReceiveNft(msg) => execute_receive_nft(deps, env, info, msg),
+        ExecuteMsg::ResetRateLimit { key } => execute_reset_rate_limit(deps, env, info, key),
+        ExecuteMsg::UpdateRateLimit { rate } => execute_update_rate_limit(deps, env, info, rate),
+        ExecuteMsg::RedeemVoucher { voucher } => execute_redeem_voucher(deps, env, voucher),
+        ExecuteMsg::SetVoucherAuthority { authority } => {
+            execute_set_voucher_authority(deps, info, authority)
+        }
+        ExecuteMsg::UpdateOrigin { origin } => execute_update_origin(deps, info, origin),
+        ExecuteMsg::ProposeAdmin { admin, expires } => {
+            execute_propose_admin(deps, env, info, admin, expires)
+        }
+        ExecuteMsg::AcceptAdmin {} => execute_accept_admin(deps, env, info),
+        ExecuteMsg::Pause { until_height } => execute_pause(deps, env, info, until_height),
+        ExecuteMsg::Unpause {} => execute_unpause(deps, info),
+        ExecuteMsg::SetPauser { pauser } => execute_set_pauser(deps, info, pauser),
+        ExecuteMsg::ImportState { config, keys } => {
+            execute_import_state(deps, env, info, config, keys)
+        }
     }
 }
 
@@ -63,21 +91,306 @@ pub fn execute_receive_nft(
     info: MessageInfo,
     msg: cw721::Cw721ReceiveMsg,
 ) -> Result<Response, ContractError> {
-    RATE_LIMIT.limit(deps.storage, &env, info.sender.as_str())?;
-    Ok(Response::default().add_message(WasmMsg::Execute {
-        contract_addr: ORIGIN.load(deps.storage)?.into_string(),
-        msg: to_binary(&ProxyExecuteMsg::ReceiveProxyNft {
-            eyeball: info.sender.into_string(),
-            msg,
-        })?,
-        funds: vec![],
-    }))
+    if pause_state(deps.as_ref(), &env)?.paused {
+        return Err(ContractError::Paused {});
+    }
+    let events = RATE_LIMIT.limit_with_event(deps.storage, &env, info.sender.as_str())?;
+    Ok(Response::default()
+        .add_events(events)
+        .add_message(WasmMsg::Execute {
+            contract_addr: ORIGIN.load(deps.storage)?.into_string(),
+            msg: to_binary(&ProxyExecuteMsg::ReceiveProxyNft {
+                eyeball: info.sender.into_string(),
+                msg,
+            })?,
+            funds: vec![],
+        }))
+}
+
+pub fn execute_reset_rate_limit(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    key: String,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info)?;
+    let event = RATE_LIMIT.reset(deps.storage, &env, &key)?;
+    Ok(Response::default()
+        .add_attribute("method", "reset_rate_limit")
+        .add_attribute("sender", info.sender)
+        .add_event(event))
+}
+
+pub fn execute_update_rate_limit(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    rate: Rate,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info)?;
+    if rate.is_zero() {
+        return Err(ContractError::ZeroRate {});
+    }
+    let (old_rate, old_units) =
+        rate_attributes(RATE_LIMIT.query_current_rate(deps.storage, env.block.height)?);
+    let (new_rate, new_units) = rate_attributes(rate);
+    RATE_LIMIT.update_rate(deps.storage, &env, rate, RateTransition::NextWindow)?;
+    Ok(Response::default()
+        .add_attribute("method", "update_rate_limit")
+        .add_attribute("sender", info.sender)
+        .add_attribute("old_rate", old_rate.to_string())
+        .add_attribute("old_units", old_units)
+        .add_attribute("new_rate", new_rate.to_string())
+        .add_attribute("new_units", new_units))
+}
+
+pub fn execute_redeem_voucher(
+    deps: DepsMut,
+    env: Env,
+    voucher: SignedVoucher,
+) -> Result<Response, ContractError> {
+    let event = RATE_LIMIT.redeem_voucher(deps.storage, deps.api, &env, &voucher)?;
+    Ok(Response::default()
+        .add_attribute("method", "redeem_voucher")
+        .add_event(event))
+}
+
+pub fn execute_set_voucher_authority(
+    deps: DepsMut,
+    info: MessageInfo,
+    authority: Option<VoucherAuthority>,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info)?;
+    RATE_LIMIT.set_voucher_authority(deps.storage, authority.as_ref())?;
+    Ok(Response::default()
+        .add_attribute("method", "set_voucher_authority")
+        .add_attribute("sender", info.sender))
+}
+
+pub fn execute_update_origin(
+    deps: DepsMut,
+    info: MessageInfo,
+    origin: String,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info)?;
+    let origin = deps.api.addr_validate(&origin)?;
+    let old = ORIGIN.load(deps.storage)?;
+    ORIGIN.save(deps.storage, &origin)?;
+    Ok(Response::default()
+        .add_attribute("method", "update_origin")
+        .add_attribute("sender", info.sender)
+        .add_attribute("old_origin", old)
+        .add_attribute("new_origin", origin))
+}
+
+pub fn execute_propose_admin(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    admin: String,
+    expires: Option<Expiration>,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info)?;
+    let address = deps.api.addr_validate(&admin)?;
+    let expires = expires.unwrap_or_default();
+    if expires.is_expired(&env.block) {
+        return Err(ContractError::AlreadyExpired {});
+    }
+    PENDING_ADMIN.save(
+        deps.storage,
+        &PendingAdmin {
+            address: address.clone(),
+            expires,
+        },
+    )?;
+    Ok(Response::default()
+        .add_attribute("method", "propose_admin")
+        .add_attribute("sender", info.sender)
+        .add_attribute("pending_admin", address)
+        .add_attribute("expires", expires.to_string()))
+}
+
+pub fn execute_accept_admin(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    match PENDING_ADMIN.may_load(deps.storage)? {
+        Some(pending)
+            if pending.address == info.sender && !pending.expires.is_expired(&env.block) =>
+        {
+            PENDING_ADMIN.remove(deps.storage);
+            let old = ADMIN.load(deps.storage)?;
+            ADMIN.save(deps.storage, &pending.address)?;
+            Ok(Response::default()
+                .add_attribute("method", "accept_admin")
+                .add_attribute("old_admin", old)
+                .add_attribute("new_admin", pending.address))
+        }
+        _ => Err(ContractError::NoPendingAdmin {}),
+    }
+}
+
+pub fn execute_pause(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    until_height: Option<u64>,
+) -> Result<Response, ContractError> {
+    assert_admin_or_pauser(deps.as_ref(), &info)?;
+    if until_height.is_some_and(|h| h <= env.block.height) {
+        return Err(ContractError::InvalidUnpauseHeight {});
+    }
+    PAUSED.save(deps.storage, &until_height)?;
+    Ok(Response::default()
+        .add_attribute("method", "pause")
+        .add_attribute("sender", info.sender)
+        .add_attribute(
+            "until_height",
+            until_height.map_or("none".to_string(), |h| h.to_string()),
+        ))
+}
+
+pub fn execute_unpause(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    assert_admin_or_pauser(deps.as_ref(), &info)?;
+    PAUSED.remove(deps.storage);
+    Ok(Response::default()
+        .add_attribute("method", "unpause")
+        .add_attribute("sender", info.sender))
+}
+
+pub fn execute_set_pauser(
+    deps: DepsMut,
+    info: MessageInfo,
+    pauser: Option<String>,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info)?;
+    match pauser {
+        Some(pauser) => PAUSER.save(deps.storage, &deps.api.addr_validate(&pauser)?)?,
+        None => PAUSER.remove(deps.storage),
+    }
+    Ok(Response::default()
+        .add_attribute("method", "set_pauser")
+        .add_attribute("sender", info.sender))
+}
+
+pub fn execute_import_state(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    config: Option<RateLimiterConfig>,
+    keys: Vec<ExportedKey>,
+) -> Result<Response, ContractError> {
+    assert_admin(deps.as_ref(), &info)?;
+    if config.as_ref().is_some_and(|c| c.rate_limit.is_zero()) {
+        return Err(ContractError::ZeroRate {});
+    }
+    RATE_LIMIT.import(deps.storage, &env, config.as_ref(), &keys)?;
+    Ok(Response::default()
+        .add_attribute("method", "import_state")
+        .add_attribute("sender", info.sender)
+        .add_attribute("keys", keys.len().to_string()))
+}
+
+fn assert_admin_or_pauser(deps: Deps, info: &MessageInfo) -> Result<(), ContractError> {
+    if ADMIN.load(deps.storage)? == info.sender
+        || PAUSER.may_load(deps.storage)?.as_ref() == Some(&info.sender)
+    {
+        Ok(())
+    } else {
+        Err(ContractError::NotPauser {})
+    }
+}
+
+fn pause_state(deps: Deps, env: &Env) -> StdResult<PauseState> {
+    Ok(match PAUSED.may_load(deps.storage)? {
+        Some(until_height) => PauseState {
+            paused: until_height.is_none_or(|h| env.block.height < h),
+            until_height,
+        },
+        None => PauseState {
+            paused: false,
+            until_height: None,
+        },
+    })
+}
+
+fn rate_attributes(rate: Rate) -> (u64, &'static str) {
+    match rate {
+        Rate::PerBlock(rate) => (rate, "nfts_per_block"),
+        Rate::Blocks(rate) => (rate, "blocks_per_nft"),
+    }
+}
+
+fn assert_admin(deps: Deps, info: &MessageInfo) -> Result<(), ContractError> {
+    if ADMIN.load(deps.storage)? != info.sender {
+        Err(ContractError::Unauthorized {})
+    } else {
+        Ok(())
+    }
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
+    let stored = get_contract_version(deps.storage)?;
+    if stored.contract != CONTRACT_NAME {
+        return Err(ContractError::WrongContract {
+            name: stored.contract,
+        });
+    }
+    let parse = |v: &str| {
+        v.parse::<semver::Version>()
+            .map_err(|e| StdError::generic_err(format!("invalid version ({v}): {e}")))
+    };
+    if parse(&stored.version)? > parse(CONTRACT_VERSION)? {
+        return Err(ContractError::Downgrade {
+            stored: stored.version,
+            version: CONTRACT_VERSION.to_string(),
+        });
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+    match msg.admin {
+        Some(admin) => ADMIN.save(deps.storage, &deps.api.addr_validate(&admin)?)?,
+        None if ADMIN.may_load(deps.storage)?.is_none() => {
+            return Err(ContractError::MissingAdmin {})
+        }
+        None => (),
+    }
+
+    let status =
+        RATE_LIMIT.migrate_storage(deps.storage, msg.batch.unwrap_or(DEFAULT_MIGRATION_BATCH))?;
+    Ok(Response::default()
+        .add_attribute("method", "migrate")
+        .add_attribute("from_version", stored.version)
+        .add_attribute("to_version", CONTRACT_VERSION)
+        .add_attribute("storage_version", status.from.to_string())
+        .add_attribute("storage_migrated", status.migrated.to_string())
+        .add_attribute("storage_migration_done", status.done.to_string()))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
+    match msg {
+        QueryMsg::Proxy(msg) => query_proxy(deps, env, msg),
+        QueryMsg::RateLimiter(msg) => RATE_LIMIT.handle_query(deps.storage, &env, msg),
+    }
+}
+
+fn query_proxy(deps: Deps, env: Env, msg: ProxyQueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::RateLimit {} => to_binary(&RATE_LIMIT.query_limit(deps.storage)?),
-        QueryMsg::Origin {} => to_binary(&ORIGIN.load(deps.storage)?),
+        ProxyQueryMsg::RateLimit {} => {
+            to_binary(&RATE_LIMIT.query_current_rate(deps.storage, env.block.height)?)
+        }
+        ProxyQueryMsg::PendingRateLimit {} => {
+            let current = RATE_LIMIT.query_current_rate(deps.storage, env.block.height)?;
+            let pending = RATE_LIMIT.query_limit(deps.storage)?;
+            to_binary(&(pending != current).then_some(pending))
+        }
+        ProxyQueryMsg::Origin {} => to_binary(&ORIGIN.load(deps.storage)?),
+        ProxyQueryMsg::Admin {} => to_binary(&ADMIN.load(deps.storage)?),
+        ProxyQueryMsg::PendingAdmin {} => to_binary(&PENDING_ADMIN.may_load(deps.storage)?),
+        ProxyQueryMsg::Pauser {} => to_binary(&PAUSER.may_load(deps.storage)?),
+        ProxyQueryMsg::Paused {} => to_binary(&pause_state(deps, &env)?),
     }
 }
@@ -11,4 +11,31 @@ pub enum ContractError {
 
     #[error("rate must be non-zero")]
     ZeroRate {},
+
+    #[error("only the admin may perform this action")]
+    Unauthorized {},
+
+    #[error("only the admin or pauser may perform this action")]
+    NotPauser {},
+
+    #[error("the proxy is paused")]
+    Paused {},
+
+    #[error("unpause height must be in the future")]
+    InvalidUnpauseHeight {},
+
+    #[error("admin proposal expiration has already passed")]
+    AlreadyExpired {},
+
+    #[error("there is no pending admin proposal for the sender")]
+    NoPendingAdmin {},
+
+    #[error("can not migrate from contract ({name})")]
+    WrongContract { name: String },
+
+    #[error("can not migrate from version ({stored}) to older version ({version})")]
+    Downgrade { stored: String, version: String },
+
+    #[error("an admin must be provided when migrating from a version without one")]
+    MissingAdmin {},
 }
@@ -1,25 +1,128 @@
 use cosmwasm_schema::{cw_serde, QueryResponses};
+use cw_utils::Expiration;
 
-use cw_rate_limiter::Rate;
+use cw_rate_limiter::{
+    ExportedKey, Rate, RateLimiterConfig, RateLimiterQueryMsg, SignedVoucher, VoucherAuthority,
+};
+
+use crate::state::{PauseState, PendingAdmin};
 
 #[cw_serde]
 pub struct InstantiateMsg {
     pub rate_limit: Rate,
     pub origin: Option<String>,
+    /// The address that may perform administrative actions. Defaults
+    /// to the instantiator.
+    pub admin: Option<String>,
 }
 
 #[cw_serde]
 pub enum ExecuteMsg {
     ReceiveNft(cw721::Cw721ReceiveMsg),
+    /// Clears the usage of a cw721 contract so it may send again
+    /// immediately. Only callable by the admin.
+    ResetRateLimit {
+        key: String,
+    },
+    /// Changes the rate limit, effective from the next block. Usage
+    /// recorded under the previous rate counts until the end of each
+    /// cw721 contract's current window. Only callable by the admin.
+    UpdateRateLimit {
+        rate: Rate,
+    },
+    /// Redeems a voucher signed by the voucher authority, letting a
+    /// cw721 contract send beyond its rate limit. Callable by anyone.
+    RedeemVoucher {
+        voucher: SignedVoucher,
+    },
+    /// Sets the public key vouchers must be signed with, or stops
+    /// accepting vouchers if `None`. Only callable by the admin.
+    SetVoucherAuthority {
+        authority: Option<VoucherAuthority>,
+    },
+    /// Sets the contract that received NFTs are forwarded to. Only
+    /// callable by the admin.
+    UpdateOrigin {
+        origin: String,
+    },
+    /// Proposes a new admin, who must accept with `AcceptAdmin` before
+    /// `expires` for the transfer to take effect. Replaces any
+    /// existing proposal. Only callable by the admin.
+    ProposeAdmin {
+        admin: String,
+        expires: Option<Expiration>,
+    },
+    /// Accepts a pending admin proposal. Only callable by the proposed
+    /// admin.
+    AcceptAdmin {},
+    /// Stops forwarding NFTs until `Unpause` is called or, if set,
+    /// until block `until_height`. Callable by the admin or the
+    /// pauser.
+    Pause {
+        until_height: Option<u64>,
+    },
+    /// Resumes forwarding NFTs. Callable by the admin or the pauser.
+    Unpause {},
+    /// Sets the address that may pause and unpause the proxy in
+    /// addition to the admin, or removes it if `None`. Only callable
+    /// by the admin.
+    SetPauser {
+        pauser: Option<String>,
+    },
+    /// Imports rate limiter state exported from another proxy with the
+    /// `Export` query. `config`, if set, replaces this contract's rate
+    /// limiter configuration, and may only be set before any keys
+    /// have been limited. Only callable by the admin.
+    ImportState {
+        config: Option<RateLimiterConfig>,
+        keys: Vec<ExportedKey>,
+    },
+}
+
+#[cw_serde]
+pub struct MigrateMsg {
+    /// Sets the admin. Required when migrating from a version that
+    /// predates the admin.
+    pub admin: Option<String>,
+    /// The maximum number of stored rate limiter entries to upgrade
+    /// to the current layout. Migrating again to the same code
+    /// continues where the last batch stopped. Defaults to 100.
+    pub batch: Option<u32>,
 }
 
 #[cw_serde]
 #[derive(QueryResponses)]
+#[serde(untagged)]
+#[query_responses(nested)]
 pub enum QueryMsg {
-    /// Gets the contract's rate limit.
+    Proxy(ProxyQueryMsg),
+    RateLimiter(RateLimiterQueryMsg),
+}
+
+#[cw_serde]
+#[derive(QueryResponses)]
+pub enum ProxyQueryMsg {
+    /// Gets the rate limit in effect for the current block.
     #[returns(Rate)]
     RateLimit {},
 
+    /// Gets the rate limit set by `UpdateRateLimit` that takes effect
+    /// from the next block, or `None` if it is already in effect.
+    #[returns(Option<Rate>)]
+    PendingRateLimit {},
+
     #[returns(String)]
     Origin {},
+
+    #[returns(String)]
+    Admin {},
+
+    #[returns(Option<PendingAdmin>)]
+    PendingAdmin {},
+
+    #[returns(Option<String>)]
+    Pauser {},
+
+    #[returns(PauseState)]
+    Paused {},
 }
@@ -1,7 +1,42 @@
+use cosmwasm_schema::cw_serde;
 use cosmwasm_std::Addr;
 use cw_storage_plus::Item;
+use cw_utils::Expiration;
 
 use cw_rate_limiter::RateLimiter;
 
-pub const RATE_LIMIT: RateLimiter = RateLimiter::new("rate_limit", "sender");
+pub const RATE_LIMIT: RateLimiter = RateLimiter::new("rate_limit", "sender")
+    .with_stats("key_stats", "total_stats")
+    .with_throttled_index("throttled")
+    .with_vouchers("voucher_authority", "voucher_nonces", "bypasses")
+    .with_rate_updates("rate_changes")
+    .with_storage_version(
+        "rate_limit_version",
+        "rate_limit_migration",
+        "rate_limit_migration_written",
+    );
 pub const ORIGIN: Item<Addr> = Item::new("origin");
+pub const ADMIN: Item<Addr> = Item::new("admin");
+pub const PENDING_ADMIN: Item<PendingAdmin> = Item::new("pending_admin");
+/// An address other than the admin that may pause and unpause the
+/// proxy.
+pub const PAUSER: Item<Addr> = Item::new("pauser");
+/// Present while paused, holding the height at which the proxy
+/// unpauses automatically, if any.
+pub const PAUSED: Item<Option<u64>> = Item::new("paused");
+
+/// An admin proposed by the current admin that has not yet accepted.
+#[cw_serde]
+pub struct PendingAdmin {
+    pub address: Addr,
+    /// When the proposal lapses if not accepted.
+    pub expires: Expiration,
+}
+
+#[cw_serde]
+pub struct PauseState {
+    pub paused: bool,
+    /// The height from which the proxy forwards NFTs again without
+    /// needing to be unpaused.
+    pub until_height: Option<u64>,
+}
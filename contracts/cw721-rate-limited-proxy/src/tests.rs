@@ -1,10 +1,25 @@
-use cosmwasm_std::{to_binary, Addr, Empty};
-use cw_multi_test::{next_block, App, Contract, ContractWrapper, Executor};
-use cw_rate_limiter::{Rate, RateLimitError};
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    from_slice, to_binary, Addr, Binary, Deps, DepsMut, Empty, Env, Event, MessageInfo, Response,
+    StdError, StdResult, WasmMsg,
+};
+use cw721_proxy::ProxyExecuteMsg;
+use cw_multi_test::{next_block, App, AppResponse, Contract, ContractWrapper, Executor};
+use cw_rate_limiter::{
+    voucher_sign_bytes, KeyState, KeyStats, Rate, RateInfo, RateLimitError, RateLimiterQueryMsg,
+    SignedVoucher, StateExport, ThrottledKey, TotalStats, Voucher, VoucherAuthority,
+    STORAGE_VERSION,
+};
+use cw_storage_plus::{Item, Map};
+use cw_utils::Expiration;
+use k256::ecdsa::{signature::DigestSigner, Signature, SigningKey};
+use sha2::{Digest, Sha256};
 
 use crate::{
     error::ContractError,
-    msg::{InstantiateMsg, QueryMsg},
+    msg::MigrateMsg,
+    msg::{ExecuteMsg, InstantiateMsg, ProxyQueryMsg, QueryMsg},
+    state::{PauseState, PendingAdmin, ORIGIN},
 };
 
 struct Test {
@@ -92,7 +107,7 @@ impl Test {
             .map(|rate_limiter| self.rate_limiter = rate_limiter)
     }
 
-    pub fn send_nft_and_check_received(&mut self, nft: Addr) -> Result<(), anyhow::Error> {
+    pub fn send_nft_and_check_received(&mut self, nft: Addr) -> Result<AppResponse, anyhow::Error> {
         self.nfts_minted += 1;
 
         self.app.execute_contract(
@@ -106,7 +121,7 @@ impl Test {
             }),
             &[],
         )?;
-        self.app.execute_contract(
+        let res = self.app.execute_contract(
             self.minter.clone(),
             nft.clone(),
             &cw721_base::msg::ExecuteMsg::<Empty, Empty>::SendNft {
@@ -136,7 +151,7 @@ impl Test {
             }
         }
 
-        Ok(())
+        Ok(res)
     }
 
     pub fn send_nfts_at_rate(
@@ -154,7 +169,7 @@ impl Test {
                     }
                 }
                 Rate::Blocks(b) => {
-                    if (self.app.block_info().height - start_block) % b == 0 {
+                    if (self.app.block_info().height - start_block).is_multiple_of(b) {
                         self.send_nft_and_check_received(cw721.clone())?;
                     }
                 }
@@ -168,7 +183,11 @@ impl Test {
 
 impl InstantiateMsg {
     fn new(rate_limit: Rate, origin: Option<String>) -> Self {
-        Self { rate_limit, origin }
+        Self {
+            rate_limit,
+            origin,
+            admin: None,
+        }
     }
 }
 
@@ -177,7 +196,112 @@ fn cw721_rate_limiter() -> Box<dyn Contract<Empty>> {
         crate::contract::execute,
         crate::contract::instantiate,
         crate::contract::query,
-    );
+    )
+    .with_migrate(crate::contract::migrate);
+    Box::new(contract)
+}
+
+// The state of a key as stored before versioning. `this_block` was
+// zero for keys limited under `Rate::Blocks`.
+#[cw_serde]
+#[derive(Default)]
+struct RateInfoV1 {
+    last_updated_height: u64,
+    this_block: u64,
+}
+
+const LEGACY_RATE_LIMIT: Item<Rate> = Item::new("rate_limit");
+const LEGACY_RATES: Map<&str, RateInfoV1> = Map::new("sender");
+
+// The rate limiter as deployed before it had an admin or versioned
+// storage, writing keys in the layout it did. Its instantiate
+// message also sets the cw2 contract name and version.
+fn cw721_rate_limiter_legacy() -> Box<dyn Contract<Empty>> {
+    fn instantiate(
+        deps: DepsMut,
+        _env: Env,
+        info: MessageInfo,
+        (name, version, msg): (String, String, InstantiateMsg),
+    ) -> Result<Response, ContractError> {
+        cw2::set_contract_version(deps.storage, name, version)?;
+        let origin = match msg.origin {
+            Some(origin) => deps.api.addr_validate(&origin)?,
+            None => info.sender,
+        };
+        ORIGIN.save(deps.storage, &origin)?;
+        LEGACY_RATE_LIMIT.save(deps.storage, &msg.rate_limit)?;
+        Ok(Response::default())
+    }
+
+    fn execute(
+        deps: DepsMut,
+        env: Env,
+        info: MessageInfo,
+        msg: ExecuteMsg,
+    ) -> Result<Response, ContractError> {
+        let msg = match msg {
+            ExecuteMsg::ReceiveNft(msg) => msg,
+            _ => return Err(StdError::generic_err("not supported before versioning").into()),
+        };
+        let key = info.sender.as_str();
+        let height = env.block.height;
+        let limited = |blocks_remaining| RateLimitError::Limited {
+            key: key.to_string(),
+            blocks_remaining,
+        };
+        let RateInfoV1 {
+            last_updated_height,
+            this_block,
+        } = LEGACY_RATES
+            .may_load(deps.storage, key)?
+            .unwrap_or_default();
+        let this_block = match LEGACY_RATE_LIMIT.load(deps.storage)? {
+            Rate::PerBlock(limit) => {
+                let this_block = if last_updated_height == height {
+                    this_block + 1
+                } else {
+                    1
+                };
+                if this_block > limit {
+                    return Err(limited(1).into());
+                }
+                this_block
+            }
+            Rate::Blocks(blocks) => {
+                let elapsed = height.saturating_sub(last_updated_height);
+                if elapsed < blocks {
+                    return Err(limited(blocks - elapsed).into());
+                }
+                0
+            }
+        };
+        LEGACY_RATES.save(
+            deps.storage,
+            key,
+            &RateInfoV1 {
+                last_updated_height: height,
+                this_block,
+            },
+        )?;
+        Ok(Response::default().add_message(WasmMsg::Execute {
+            contract_addr: ORIGIN.load(deps.storage)?.into_string(),
+            msg: to_binary(&ProxyExecuteMsg::ReceiveProxyNft {
+                eyeball: info.sender.into_string(),
+                msg,
+            })?,
+            funds: vec![],
+        }))
+    }
+
+    fn query(deps: Deps, _env: Env, msg: ProxyQueryMsg) -> StdResult<Binary> {
+        match msg {
+            ProxyQueryMsg::RateLimit {} => to_binary(&LEGACY_RATE_LIMIT.load(deps.storage)?),
+            ProxyQueryMsg::Origin {} => to_binary(&ORIGIN.load(deps.storage)?),
+            _ => Err(StdError::generic_err("not supported before versioning")),
+        }
+    }
+
+    let contract = ContractWrapper::new(execute, instantiate, query);
     Box::new(contract)
 }
 
@@ -216,7 +340,7 @@ fn random_rate<R: rand::Rng, S: rand::distributions::uniform::SampleRange<u64>>(
 fn simple_send() {
     let mut test = Test::new(1, Rate::Blocks(1));
     test.send_nft_and_check_received(test.cw721s[0].clone())
-        .unwrap()
+        .unwrap();
 }
 
 #[test]
@@ -247,6 +371,33 @@ fn test_simple_rate_limited() {
     )
 }
 
+#[test]
+fn test_rate_limit_event() {
+    let mut test = Test::new(1, Rate::PerBlock(3));
+    let height = test.app.block_info().height;
+    let res = test
+        .send_nft_and_check_received(test.cw721s[0].clone())
+        .unwrap();
+    let event = res
+        .events
+        .iter()
+        .find(|e| e.ty == "wasm-rate_limit")
+        .unwrap();
+    let attr = |k: &str| {
+        event
+            .attributes
+            .iter()
+            .find(|a| a.key == k)
+            .unwrap()
+            .value
+            .clone()
+    };
+    assert_eq!(attr("key"), test.cw721s[0].to_string());
+    assert_eq!(attr("used"), "1");
+    assert_eq!(attr("remaining"), "2");
+    assert_eq!(attr("window_end"), (height + 1).to_string());
+}
+
 #[test]
 fn test_multikey_rate_limit() {
     let rate_limit = Rate::PerBlock(2);
@@ -322,7 +473,7 @@ fn fuzz_rate_limiting() {
 
         // Open state for next iteration.
         if let Rate::Blocks(blocks) = limit {
-            test.app.update_block(|mut b| b.height += blocks);
+            test.app.update_block(|b| b.height += blocks);
         } else {
             test.app.update_block(next_block)
         }
@@ -343,6 +494,7 @@ fn test_origin_instantiator() {
             &InstantiateMsg {
                 rate_limit: Rate::Blocks(20),
                 origin: None,
+                admin: None,
             },
             &[],
             "rate limiter",
@@ -352,13 +504,19 @@ fn test_origin_instantiator() {
 
     let origin: Addr = app
         .wrap()
-        .query_wasm_smart(&rate_limiter, &QueryMsg::Origin {})
+        .query_wasm_smart(&rate_limiter, &QueryMsg::Proxy(ProxyQueryMsg::Origin {}))
         .unwrap();
     assert_eq!(origin, Addr::unchecked("ekez"));
 
+    let admin: Addr = app
+        .wrap()
+        .query_wasm_smart(&rate_limiter, &QueryMsg::Proxy(ProxyQueryMsg::Admin {}))
+        .unwrap();
+    assert_eq!(admin, Addr::unchecked("ekez"));
+
     let rate: Rate = app
         .wrap()
-        .query_wasm_smart(&rate_limiter, &QueryMsg::RateLimit {})
+        .query_wasm_smart(&rate_limiter, &QueryMsg::Proxy(ProxyQueryMsg::RateLimit {}))
         .unwrap();
     assert_eq!(rate, Rate::Blocks(20))
 }
@@ -377,6 +535,7 @@ fn test_origin_specified() {
             &InstantiateMsg {
                 rate_limit: Rate::PerBlock(20),
                 origin: Some("ekez".to_string()),
+                admin: None,
             },
             &[],
             "rate limiter",
@@ -386,13 +545,13 @@ fn test_origin_specified() {
 
     let origin: Addr = app
         .wrap()
-        .query_wasm_smart(&rate_limiter, &QueryMsg::Origin {})
+        .query_wasm_smart(&rate_limiter, &QueryMsg::Proxy(ProxyQueryMsg::Origin {}))
         .unwrap();
     assert_eq!(origin, Addr::unchecked("ekez"));
 
     let rate: Rate = app
         .wrap()
-        .query_wasm_smart(&rate_limiter, &QueryMsg::RateLimit {})
+        .query_wasm_smart(&rate_limiter, &QueryMsg::Proxy(ProxyQueryMsg::RateLimit {}))
         .unwrap();
     assert_eq!(rate, Rate::PerBlock(20))
 }
@@ -409,6 +568,7 @@ fn test_zero_rate_instantiate() {
             &InstantiateMsg {
                 rate_limit: Rate::PerBlock(0),
                 origin: Some("ekez".to_string()),
+                admin: None,
             },
             &[],
             "rate limiter",
@@ -428,6 +588,7 @@ fn test_zero_rate_instantiate() {
         &InstantiateMsg {
             rate_limit: infinity,
             origin: Some("ekez".to_string()),
+            admin: None,
         },
         &[],
         "rate limiter",
@@ -435,3 +596,987 @@ fn test_zero_rate_instantiate() {
     )
     .unwrap();
 }
+
+#[test]
+fn test_stats() {
+    let mut test = Test::new(2, Rate::PerBlock(1));
+    let start = test.app.block_info().height;
+
+    test.send_nft_and_check_received(test.cw721s[0].clone())
+        .unwrap();
+    test.send_nft_and_check_received(test.cw721s[0].clone())
+        .unwrap_err();
+    test.app.update_block(next_block);
+    test.send_nft_and_check_received(test.cw721s[0].clone())
+        .unwrap();
+
+    let stats: Option<KeyStats> = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::KeyStats {
+                key: test.cw721s[0].to_string(),
+            }),
+        )
+        .unwrap();
+    // The rejected send was reverted along with its count.
+    assert_eq!(
+        stats,
+        Some(KeyStats {
+            accepted: 2,
+            rejected: 0,
+            first_seen_height: start,
+            last_seen_height: start + 1,
+        })
+    );
+
+    let stats: Option<KeyStats> = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::KeyStats {
+                key: test.cw721s[1].to_string(),
+            }),
+        )
+        .unwrap();
+    assert_eq!(stats, None);
+
+    let totals: TotalStats = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::TotalStats {}),
+        )
+        .unwrap();
+    assert_eq!(
+        totals,
+        TotalStats {
+            accepted: 2,
+            rejected: 0,
+            keys: 1,
+        }
+    );
+}
+
+#[test]
+fn test_rate_limiter_queries() {
+    let mut test = Test::new(2, Rate::PerBlock(2));
+    test.send_nft_and_check_received(test.cw721s[1].clone())
+        .unwrap();
+
+    let keys: Vec<KeyState> = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::Keys {
+                start_after: None,
+                limit: None,
+            }),
+        )
+        .unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].key, test.cw721s[1].to_string());
+    assert_eq!(keys[0].usage.remaining, 1);
+
+    // Flat JSON for both the proxy's and the rate limiter's queries.
+    let rate: Rate = test
+        .app
+        .wrap()
+        .query_wasm_smart(&test.rate_limiter, &ProxyQueryMsg::RateLimit {})
+        .unwrap();
+    assert_eq!(rate, Rate::PerBlock(2));
+    let state: KeyState = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &RateLimiterQueryMsg::KeyState {
+                key: test.cw721s[0].to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(state.info, None);
+}
+
+#[test]
+fn test_reset_rate_limit() {
+    let mut test = Test::new(1, Rate::Blocks(10));
+    let cw721 = test.cw721s[0].clone();
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721.clone()).unwrap_err();
+
+    let err: ContractError = test
+        .app
+        .execute_contract(
+            Addr::unchecked("ekez"),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::ResetRateLimit {
+                key: cw721.to_string(),
+            },
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    let res = test
+        .app
+        .execute_contract(
+            test.minter.clone(),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::ResetRateLimit {
+                key: cw721.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    assert!(
+        res.has_event(&Event::new("wasm-rate_limit_reset").add_attribute("key", cw721.to_string()))
+    );
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721).unwrap_err();
+}
+
+#[test]
+fn test_throttled_query() {
+    let mut test = Test::new(2, Rate::Blocks(5));
+    let height = test.app.block_info().height;
+    test.send_nft_and_check_received(test.cw721s[1].clone())
+        .unwrap();
+    test.app.update_block(next_block);
+    test.send_nft_and_check_received(test.cw721s[0].clone())
+        .unwrap();
+
+    let throttled: Vec<ThrottledKey> = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::Throttled {
+                start_after: None,
+                limit: None,
+            }),
+        )
+        .unwrap();
+    assert_eq!(
+        throttled,
+        vec![
+            ThrottledKey {
+                key: test.cw721s[1].to_string(),
+                available_at: height + 5,
+            },
+            ThrottledKey {
+                key: test.cw721s[0].to_string(),
+                available_at: height + 6,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_export_import() {
+    let mut test = Test::new(3, Rate::Blocks(10));
+    for cw721 in test.cw721s.clone() {
+        test.send_nft_and_check_received(cw721).unwrap();
+    }
+    let old = test.rate_limiter.clone();
+    test.update_rate(Rate::PerBlock(100)).unwrap();
+    for cw721 in test.cw721s.clone() {
+        test.send_nft_and_check_received(cw721).unwrap();
+    }
+
+    let export = |test: &Test, addr: &Addr, start_after: Option<String>| -> StateExport {
+        test.app
+            .wrap()
+            .query_wasm_smart(
+                addr,
+                &QueryMsg::RateLimiter(RateLimiterQueryMsg::Export {
+                    start_after,
+                    limit: Some(2),
+                }),
+            )
+            .unwrap()
+    };
+    let first = export(&test, &old, None);
+    assert_eq!(first.keys.len(), 2);
+
+    let err: ContractError = test
+        .app
+        .execute_contract(
+            Addr::unchecked("ekez"),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::ImportState {
+                config: Some(first.config.clone()),
+                keys: first.keys.clone(),
+            },
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // The new proxy has already limited keys, which would be read
+    // under the imported rate.
+    let err: ContractError = test
+        .app
+        .execute_contract(
+            test.minter.clone(),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::ImportState {
+                config: Some(first.config.clone()),
+                keys: first.keys.clone(),
+            },
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        ContractError::Std(StdError::generic_err(
+            "can not import a config into a rate limiter that has keys"
+        ))
+    );
+    test.update_rate(Rate::PerBlock(100)).unwrap();
+
+    let mut page = first;
+    let mut config = Some(page.config.clone());
+    loop {
+        test.app
+            .execute_contract(
+                test.minter.clone(),
+                test.rate_limiter.clone(),
+                &ExecuteMsg::ImportState {
+                    config: config.take(),
+                    keys: page.keys,
+                },
+                &[],
+            )
+            .unwrap();
+        match page.next {
+            Some(next) => page = export(&test, &old, Some(next)),
+            None => break,
+        }
+    }
+
+    let rate: Rate = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::Proxy(ProxyQueryMsg::RateLimit {}),
+        )
+        .unwrap();
+    assert_eq!(rate, Rate::Blocks(10));
+    for cw721 in test.cw721s.clone() {
+        test.send_nft_and_check_received(cw721).unwrap_err();
+    }
+}
+
+#[test]
+fn test_update_rate_limit() {
+    let mut test = Test::new(1, Rate::Blocks(10));
+    let cw721 = test.cw721s[0].clone();
+    let proxy = test.rate_limiter.clone();
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+
+    let update = |test: &mut Test, sender: &Addr, rate: Rate| {
+        test.app.execute_contract(
+            sender.clone(),
+            proxy.clone(),
+            &ExecuteMsg::UpdateRateLimit { rate },
+            &[],
+        )
+    };
+    let err: ContractError = update(&mut test, &Addr::unchecked("ekez"), Rate::PerBlock(1))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let minter = test.minter.clone();
+    let err: ContractError = update(&mut test, &minter, Rate::PerBlock(0))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::ZeroRate {});
+
+    // Updates in the same block all replace the rate in effect during
+    // it.
+    update(&mut test, &minter, Rate::PerBlock(5)).unwrap();
+    let res = update(&mut test, &minter, Rate::PerBlock(2)).unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("old_rate", "10")
+            .add_attribute("old_units", "blocks_per_nft")
+            .add_attribute("new_rate", "2")
+            .add_attribute("new_units", "nfts_per_block")
+    ));
+    let rates = |test: &Test| -> (Rate, Option<Rate>) {
+        let wrap = test.app.wrap();
+        (
+            wrap.query_wasm_smart(&proxy, &QueryMsg::Proxy(ProxyQueryMsg::RateLimit {}))
+                .unwrap(),
+            wrap.query_wasm_smart(&proxy, &QueryMsg::Proxy(ProxyQueryMsg::PendingRateLimit {}))
+                .unwrap(),
+        )
+    };
+    assert_eq!(rates(&test), (Rate::Blocks(10), Some(Rate::PerBlock(2))));
+
+    // The cw721 finishes its current window under the old rate.
+    test.app.update_block(next_block);
+    assert_eq!(rates(&test), (Rate::PerBlock(2), None));
+    test.send_nft_and_check_received(cw721.clone()).unwrap_err();
+    test.app.update_block(|b| b.height += 9);
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721).unwrap_err();
+    assert_eq!(test.rate_limiter, proxy);
+}
+
+#[test]
+fn test_update_origin() {
+    let mut test = Test::new(1, Rate::PerBlock(10));
+    let cw721 = test.cw721s[0].clone();
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+
+    let tester_id = test.app.store_code(cw721_proxy_tester());
+    let receiver = test
+        .app
+        .instantiate_contract(
+            tester_id,
+            test.minter.clone(),
+            &cw721_proxy_tester::msg::InstantiateMsg::default(),
+            &[],
+            "proxy_tester",
+            None,
+        )
+        .unwrap();
+
+    let err: ContractError = test
+        .app
+        .execute_contract(
+            Addr::unchecked("ekez"),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::UpdateOrigin {
+                origin: receiver.to_string(),
+            },
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    test.app
+        .execute_contract(
+            test.minter.clone(),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::UpdateOrigin {
+                origin: receiver.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+    let origin: Addr = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::Proxy(ProxyQueryMsg::Origin {}),
+        )
+        .unwrap();
+    assert_eq!(origin, receiver);
+
+    test.mock_receiver = receiver;
+    test.send_nft_and_check_received(cw721).unwrap();
+}
+
+#[test]
+fn test_admin_transfer() {
+    let mut test = Test::new(1, Rate::PerBlock(10));
+    let proxy = test.rate_limiter.clone();
+    let minter = test.minter.clone();
+    let ekez = Addr::unchecked("ekez");
+    let zeke = Addr::unchecked("zeke");
+
+    let propose = |test: &mut Test, sender: &Addr, admin: &Addr, expires: Option<Expiration>| {
+        test.app.execute_contract(
+            sender.clone(),
+            proxy.clone(),
+            &ExecuteMsg::ProposeAdmin {
+                admin: admin.to_string(),
+                expires,
+            },
+            &[],
+        )
+    };
+    let accept = |test: &mut Test, sender: &Addr| -> Result<AppResponse, ContractError> {
+        test.app
+            .execute_contract(
+                sender.clone(),
+                proxy.clone(),
+                &ExecuteMsg::AcceptAdmin {},
+                &[],
+            )
+            .map_err(|e| e.downcast().unwrap())
+    };
+    let admin = |test: &Test| -> Addr {
+        test.app
+            .wrap()
+            .query_wasm_smart(&proxy, &QueryMsg::Proxy(ProxyQueryMsg::Admin {}))
+            .unwrap()
+    };
+
+    let err: ContractError = propose(&mut test, &ekez, &ekez, None)
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+    assert_eq!(
+        accept(&mut test, &ekez).unwrap_err(),
+        ContractError::NoPendingAdmin {}
+    );
+
+    let height = test.app.block_info().height;
+    let err: ContractError = propose(
+        &mut test,
+        &minter,
+        &ekez,
+        Some(Expiration::AtHeight(height)),
+    )
+    .unwrap_err()
+    .downcast()
+    .unwrap();
+    assert_eq!(err, ContractError::AlreadyExpired {});
+
+    // Proposals lapse if not accepted in time.
+    propose(
+        &mut test,
+        &minter,
+        &ekez,
+        Some(Expiration::AtHeight(height + 1)),
+    )
+    .unwrap();
+    let pending: Option<PendingAdmin> = test
+        .app
+        .wrap()
+        .query_wasm_smart(&proxy, &QueryMsg::Proxy(ProxyQueryMsg::PendingAdmin {}))
+        .unwrap();
+    assert_eq!(
+        pending,
+        Some(PendingAdmin {
+            address: ekez.clone(),
+            expires: Expiration::AtHeight(height + 1),
+        })
+    );
+    test.app.update_block(next_block);
+    assert_eq!(
+        accept(&mut test, &ekez).unwrap_err(),
+        ContractError::NoPendingAdmin {}
+    );
+
+    // Only the proposed admin may accept, and the current admin keeps
+    // their role until they do.
+    propose(&mut test, &minter, &ekez, None).unwrap();
+    assert_eq!(
+        accept(&mut test, &zeke).unwrap_err(),
+        ContractError::NoPendingAdmin {}
+    );
+    assert_eq!(admin(&test), minter);
+    accept(&mut test, &ekez).unwrap();
+    assert_eq!(admin(&test), ekez);
+    assert_eq!(
+        accept(&mut test, &ekez).unwrap_err(),
+        ContractError::NoPendingAdmin {}
+    );
+
+    let err: ContractError = propose(&mut test, &minter, &zeke, None)
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+    test.app
+        .execute_contract(
+            ekez,
+            proxy,
+            &ExecuteMsg::UpdateRateLimit {
+                rate: Rate::PerBlock(1),
+            },
+            &[],
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_migrate() {
+    let mut app = App::default();
+    let minter = Addr::unchecked("minter");
+    let code_id = app.store_code(cw721_rate_limiter());
+    let proxy = app
+        .instantiate_contract(
+            code_id,
+            minter.clone(),
+            &InstantiateMsg::new(Rate::PerBlock(1), None),
+            &[],
+            "rate_limiter",
+            Some(minter.to_string()),
+        )
+        .unwrap();
+
+    // Migrating to the same version leaves state unchanged.
+    let res = app
+        .migrate_contract(
+            minter.clone(),
+            proxy.clone(),
+            &MigrateMsg {
+                admin: None,
+                batch: None,
+            },
+            code_id,
+        )
+        .unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("from_version", env!("CARGO_PKG_VERSION"))
+            .add_attribute("storage_version", STORAGE_VERSION.to_string())
+            .add_attribute("storage_migration_done", "true")
+    ));
+    let admin: Addr = app
+        .wrap()
+        .query_wasm_smart(&proxy, &QueryMsg::Proxy(ProxyQueryMsg::Admin {}))
+        .unwrap();
+    assert_eq!(admin, minter);
+}
+
+// Replaces TEST's rate limiter with a legacy one limiting at RATE.
+fn instantiate_legacy(test: &mut Test, rate: Rate) {
+    let legacy_id = test.app.store_code(cw721_rate_limiter_legacy());
+    test.rate_limiter = test
+        .app
+        .instantiate_contract(
+            legacy_id,
+            test.minter.clone(),
+            &(
+                "crates.io:cw721-proxy-rate-limit",
+                "0.0.0",
+                InstantiateMsg::new(rate, Some(test.mock_receiver.to_string())),
+            ),
+            &[],
+            "rate_limiter",
+            Some(test.minter.to_string()),
+        )
+        .unwrap();
+}
+
+// Reads CW721's state as stored by a legacy rate limiter.
+fn legacy_info(test: &Test, cw721: &Addr) -> Option<RateInfoV1> {
+    let key = LEGACY_RATES.key(cw721.as_str()).to_vec();
+    test.app
+        .wrap()
+        .query_wasm_raw(&test.rate_limiter, key)
+        .unwrap()
+        .map(|value| from_slice(&value).unwrap())
+}
+
+fn key_info(test: &Test, cw721: &Addr) -> Option<RateInfo> {
+    let state: KeyState = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::KeyState {
+                key: cw721.to_string(),
+            }),
+        )
+        .unwrap();
+    state.info
+}
+
+#[test]
+fn test_migrate_legacy() {
+    let mut test = Test::new(2, Rate::Blocks(10));
+    let minter = test.minter.clone();
+    instantiate_legacy(&mut test, Rate::Blocks(10));
+    for cw721 in test.cw721s.clone() {
+        test.send_nft_and_check_received(cw721).unwrap();
+    }
+    let height = test.app.block_info().height;
+    for cw721 in &test.cw721s {
+        assert_eq!(
+            legacy_info(&test, cw721),
+            Some(RateInfoV1 {
+                last_updated_height: height,
+                this_block: 0,
+            })
+        );
+    }
+
+    let migrate = |test: &mut Test, admin: Option<&Addr>| {
+        test.app.migrate_contract(
+            minter.clone(),
+            test.rate_limiter.clone(),
+            &MigrateMsg {
+                admin: admin.map(Addr::to_string),
+                batch: Some(1),
+            },
+            test.rate_limiter_id,
+        )
+    };
+
+    let err: ContractError = migrate(&mut test, None).unwrap_err().downcast().unwrap();
+    assert_eq!(err, ContractError::MissingAdmin {});
+
+    // Large states are migrated in batches.
+    let ekez = Addr::unchecked("ekez");
+    let res = migrate(&mut test, Some(&ekez)).unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("from_version", "0.0.0")
+            .add_attribute("storage_version", "1")
+            .add_attribute("storage_migrated", "1")
+            .add_attribute("storage_migration_done", "false")
+    ));
+    let res = migrate(&mut test, None).unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("from_version", env!("CARGO_PKG_VERSION"))
+            .add_attribute("storage_version", "1")
+            .add_attribute("storage_migrated", "1")
+            .add_attribute("storage_migration_done", "true")
+    ));
+    let res = migrate(&mut test, None).unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm")
+            .add_attribute("storage_version", STORAGE_VERSION.to_string())
+            .add_attribute("storage_migrated", "0")
+    ));
+
+    let admin: Addr = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::Proxy(ProxyQueryMsg::Admin {}),
+        )
+        .unwrap();
+    assert_eq!(admin, ekez);
+    for cw721 in &test.cw721s {
+        assert_eq!(
+            key_info(&test, cw721),
+            Some(RateInfo {
+                last_updated_height: height,
+                this_block: 1,
+                credit: 0,
+            })
+        );
+    }
+    // Usage recorded before the migration still counts.
+    for cw721 in test.cw721s.clone() {
+        test.send_nft_and_check_received(cw721).unwrap_err();
+    }
+}
+
+#[test]
+fn test_migrate_legacy_per_block() {
+    let mut test = Test::new(1, Rate::PerBlock(2));
+    let cw721 = test.cw721s[0].clone();
+    let minter = test.minter.clone();
+    instantiate_legacy(&mut test, Rate::PerBlock(2));
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721.clone()).unwrap_err();
+    let height = test.app.block_info().height;
+    assert_eq!(
+        legacy_info(&test, &cw721),
+        Some(RateInfoV1 {
+            last_updated_height: height,
+            this_block: 2,
+        })
+    );
+
+    test.app
+        .migrate_contract(
+            minter.clone(),
+            test.rate_limiter.clone(),
+            &MigrateMsg {
+                admin: Some(minter.to_string()),
+                batch: None,
+            },
+            test.rate_limiter_id,
+        )
+        .unwrap();
+
+    // Counts under `Rate::PerBlock` are kept as-is.
+    assert_eq!(
+        key_info(&test, &cw721),
+        Some(RateInfo {
+            last_updated_height: height,
+            this_block: 2,
+            credit: 0,
+        })
+    );
+    test.send_nft_and_check_received(cw721.clone()).unwrap_err();
+    test.app.update_block(next_block);
+    test.send_nft_and_check_received(cw721).unwrap();
+}
+
+#[test]
+fn test_migrate_invalid() {
+    for (name, version, expected) in [
+        (
+            "crates.io:cw721-proxy-rate-limit",
+            "99.0.0",
+            ContractError::Downgrade {
+                stored: "99.0.0".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+        ),
+        (
+            "crates.io:cw721-base",
+            "0.0.1",
+            ContractError::WrongContract {
+                name: "crates.io:cw721-base".to_string(),
+            },
+        ),
+    ] {
+        let mut app = App::default();
+        let minter = Addr::unchecked("minter");
+        let legacy_id = app.store_code(cw721_rate_limiter_legacy());
+        let code_id = app.store_code(cw721_rate_limiter());
+        let proxy = app
+            .instantiate_contract(
+                legacy_id,
+                minter.clone(),
+                &(name, version, InstantiateMsg::new(Rate::PerBlock(1), None)),
+                &[],
+                "rate_limiter",
+                Some(minter.to_string()),
+            )
+            .unwrap();
+        let err: ContractError = app
+            .migrate_contract(
+                minter.clone(),
+                proxy,
+                &MigrateMsg {
+                    admin: Some(minter.to_string()),
+                    batch: None,
+                },
+                code_id,
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(err, expected);
+    }
+}
+
+#[test]
+fn test_pause() {
+    let mut test = Test::new(1, Rate::PerBlock(10));
+    let cw721 = test.cw721s[0].clone();
+    let proxy = test.rate_limiter.clone();
+    let minter = test.minter.clone();
+    let pauser = Addr::unchecked("pauser");
+
+    let execute = |test: &mut Test, sender: &Addr, msg: ExecuteMsg| {
+        test.app
+            .execute_contract(sender.clone(), proxy.clone(), &msg, &[])
+            .map_err(|e| e.downcast::<ContractError>().unwrap())
+    };
+    let paused = |test: &Test| -> PauseState {
+        test.app
+            .wrap()
+            .query_wasm_smart(&proxy, &QueryMsg::Proxy(ProxyQueryMsg::Paused {}))
+            .unwrap()
+    };
+    let send = |test: &mut Test| -> Result<AppResponse, ContractError> {
+        test.send_nft_and_check_received(cw721.clone())
+            .map_err(|e| e.downcast::<ContractError>().unwrap())
+    };
+
+    let err = execute(&mut test, &pauser, ExecuteMsg::Pause { until_height: None }).unwrap_err();
+    assert_eq!(err, ContractError::NotPauser {});
+    let err = execute(
+        &mut test,
+        &pauser,
+        ExecuteMsg::SetPauser {
+            pauser: Some(pauser.to_string()),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(
+        &mut test,
+        &minter,
+        ExecuteMsg::SetPauser {
+            pauser: Some(pauser.to_string()),
+        },
+    )
+    .unwrap();
+    let stored: Option<Addr> = test
+        .app
+        .wrap()
+        .query_wasm_smart(&proxy, &QueryMsg::Proxy(ProxyQueryMsg::Pauser {}))
+        .unwrap();
+    assert_eq!(stored, Some(pauser.clone()));
+
+    // Pausing indefinitely lasts until unpaused.
+    execute(&mut test, &pauser, ExecuteMsg::Pause { until_height: None }).unwrap();
+    assert_eq!(
+        paused(&test),
+        PauseState {
+            paused: true,
+            until_height: None
+        }
+    );
+    assert_eq!(send(&mut test).unwrap_err(), ContractError::Paused {});
+    test.app.update_block(|b| b.height += 1000);
+    assert_eq!(send(&mut test).unwrap_err(), ContractError::Paused {});
+    execute(&mut test, &minter, ExecuteMsg::Unpause {}).unwrap();
+    assert!(!paused(&test).paused);
+    send(&mut test).unwrap();
+
+    // Pauses with a height end automatically.
+    let height = test.app.block_info().height;
+    let err = execute(
+        &mut test,
+        &pauser,
+        ExecuteMsg::Pause {
+            until_height: Some(height),
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err, ContractError::InvalidUnpauseHeight {});
+    execute(
+        &mut test,
+        &pauser,
+        ExecuteMsg::Pause {
+            until_height: Some(height + 2),
+        },
+    )
+    .unwrap();
+    test.app.update_block(next_block);
+    assert_eq!(send(&mut test).unwrap_err(), ContractError::Paused {});
+    test.app.update_block(next_block);
+    assert_eq!(
+        paused(&test),
+        PauseState {
+            paused: false,
+            until_height: Some(height + 2)
+        }
+    );
+    send(&mut test).unwrap();
+
+    // Removing the pauser revokes its ability to pause.
+    execute(&mut test, &minter, ExecuteMsg::SetPauser { pauser: None }).unwrap();
+    let err = execute(&mut test, &pauser, ExecuteMsg::Pause { until_height: None }).unwrap_err();
+    assert_eq!(err, ContractError::NotPauser {});
+}
+
+#[test]
+fn test_voucher() {
+    let mut test = Test::new(1, Rate::Blocks(10));
+    let cw721 = test.cw721s[0].clone();
+    let key = SigningKey::from_bytes(&[7; 32]).unwrap();
+    let authority = VoucherAuthority::Secp256k1 {
+        pubkey: Binary(key.verifying_key().to_bytes().to_vec()),
+    };
+
+    let err: ContractError = test
+        .app
+        .execute_contract(
+            Addr::unchecked("ekez"),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::SetVoucherAuthority {
+                authority: Some(authority.clone()),
+            },
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, ContractError::Unauthorized {});
+    test.app
+        .execute_contract(
+            test.minter.clone(),
+            test.rate_limiter.clone(),
+            &ExecuteMsg::SetVoucherAuthority {
+                authority: Some(authority),
+            },
+            &[],
+        )
+        .unwrap();
+
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721.clone()).unwrap_err();
+
+    let voucher = Voucher {
+        key: cw721.to_string(),
+        units: 1,
+        expires_at_height: test.app.block_info().height + 1,
+        nonce: 1,
+    };
+    let bytes = voucher_sign_bytes(
+        &test.app.block_info().chain_id,
+        &test.rate_limiter,
+        &voucher,
+    )
+    .unwrap();
+    let signature: Signature = key.sign_digest(Sha256::new().chain_update(bytes));
+    let redeem = ExecuteMsg::RedeemVoucher {
+        voucher: SignedVoucher {
+            voucher,
+            signature: Binary(signature.as_ref().to_vec()),
+        },
+    };
+    // Anyone may redeem a voucher, but only once.
+    let res = test
+        .app
+        .execute_contract(
+            Addr::unchecked("ekez"),
+            test.rate_limiter.clone(),
+            &redeem,
+            &[],
+        )
+        .unwrap();
+    assert!(res.has_event(
+        &Event::new("wasm-rate_limit_voucher")
+            .add_attribute("key", cw721.to_string())
+            .add_attribute("units", "1")
+            .add_attribute("nonce", "1")
+    ));
+    let err: ContractError = test
+        .app
+        .execute_contract(
+            Addr::unchecked("ekez"),
+            test.rate_limiter.clone(),
+            &redeem,
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        ContractError::Rate(RateLimitError::VoucherUsed { nonce: 1 })
+    );
+
+    test.send_nft_and_check_received(cw721.clone()).unwrap();
+    test.send_nft_and_check_received(cw721).unwrap_err();
+
+    let used: bool = test
+        .app
+        .wrap()
+        .query_wasm_smart(
+            &test.rate_limiter,
+            &QueryMsg::RateLimiter(RateLimiterQueryMsg::VoucherUsed { nonce: 1 }),
+        )
+        .unwrap();
+    assert!(used);
+}
@@ -0,0 +1,600 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Decimal, Env, Event, StdError, StdResult, Storage, Uint128};
+use cw_storage_plus::Item;
+
+use crate::boosts::translate;
+use crate::update::Effective;
+use crate::{Rate, RateInfo, RateLimiter};
+
+/// Adjusts the per-block limit of every key based on aggregate
+/// traffic. At the end of each window, the limit is multiplied by
+/// `backoff` if more than `target` units were limited across all
+/// keys, and otherwise grows by `increase`, staying within `min` and
+/// `max`.
+#[cw_serde]
+pub struct AdaptiveRate {
+    /// The lowest per-block limit. Must be non-zero.
+    pub min: u64,
+    /// The highest per-block limit, and the limit before any traffic
+    /// has been seen.
+    pub max: u64,
+    /// Length of a window in blocks. Windows are aligned to
+    /// multiples of this value. Must be non-zero.
+    pub window_blocks: u64,
+    /// Units per window across all keys above which traffic is
+    /// considered a surge.
+    pub target: u64,
+    /// Amount the limit grows by after a window without a surge.
+    pub increase: u64,
+    /// Factor the limit is multiplied by after a surge. Must be less
+    /// than one.
+    pub backoff: Decimal,
+}
+
+#[cw_serde]
+pub struct AdaptiveRateState {
+    /// The adaptive rate's configuration, if one is set.
+    pub adaptive_rate: Option<AdaptiveRate>,
+    /// The rate currently in effect for every key, if an adaptive
+    /// rate is set.
+    pub rate: Option<Rate>,
+}
+
+#[cw_serde]
+struct AdaptiveWindow {
+    limit: u64,
+    window_start: u64,
+    total: u64,
+    // The limit before a change that has not yet been reported in a
+    // `rate_limit_adapted` event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unreported: Option<u64>,
+}
+
+// The last time the adaptive rate was set or removed. PREVIOUS, the
+// rate in effect before, stays in effect until the end of HEIGHT.
+#[cw_serde]
+struct AdaptiveSwitch {
+    height: u64,
+    previous: Rate,
+}
+
+pub(crate) struct Adaptive<'a> {
+    config: Item<'a, AdaptiveRate>,
+    window: Item<'a, AdaptiveWindow>,
+    switch: Item<'a, AdaptiveSwitch>,
+}
+
+impl AdaptiveRate {
+    // The state of the window containing HEIGHT, given the state of
+    // an earlier one.
+    fn advance(&self, window: Option<AdaptiveWindow>, height: u64) -> AdaptiveWindow {
+        let window_start = height - height % self.window_blocks;
+        let mut window = match window {
+            Some(window) => window,
+            None => {
+                return AdaptiveWindow {
+                    limit: self.max,
+                    window_start,
+                    total: 0,
+                    unreported: None,
+                }
+            }
+        };
+        let mut limit = window.limit.clamp(self.min, self.max);
+        if window.window_start != window_start {
+            limit = if window.total > self.target {
+                (Uint128::from(limit) * self.backoff).u128() as u64
+            } else {
+                limit.saturating_add(self.increase)
+            };
+            // Windows without any traffic in between are quiet. The
+            // stored window may not be aligned to `window_blocks` if
+            // it was written under an earlier config.
+            let skipped = window_start
+                .saturating_sub(window.window_start)
+                .checked_div(self.window_blocks)
+                .unwrap_or(0)
+                .saturating_sub(1);
+            limit = limit
+                .saturating_add(self.increase.saturating_mul(skipped))
+                .clamp(self.min, self.max);
+            window.window_start = window_start;
+            window.total = 0;
+        }
+        window.limit = limit;
+        window
+    }
+}
+
+impl<'a> Adaptive<'a> {
+    pub(crate) const fn new(config_key: &'a str, window_key: &'a str, switch_key: &'a str) -> Self {
+        Self {
+            config: Item::new(config_key),
+            window: Item::new(window_key),
+            switch: Item::new(switch_key),
+        }
+    }
+
+    pub(crate) fn config(&self, storage: &dyn Storage) -> StdResult<Option<AdaptiveRate>> {
+        self.config.may_load(storage)
+    }
+
+    /// The rate in effect at HEIGHT if the adaptive rate was set or
+    /// removed during it.
+    pub(crate) fn switching_at(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+    ) -> StdResult<Option<Rate>> {
+        Ok(self
+            .switch
+            .may_load(storage)?
+            .filter(|switch| switch.height >= height)
+            .map(|switch| switch.previous))
+    }
+
+    /// Translates INFO, last updated under the rate in effect before
+    /// the adaptive rate was last set or removed, to RATE at HEIGHT.
+    /// Returns `None` if INFO was updated after that, or the switch
+    /// did not change the kind of rate.
+    pub(crate) fn translate(
+        &self,
+        storage: &dyn Storage,
+        rate: Rate,
+        info: &RateInfo,
+        height: u64,
+    ) -> StdResult<Option<Effective>> {
+        let switch = match self.switch.may_load(storage)? {
+            Some(switch) if info.last_updated_height <= switch.height && switch.height < height => {
+                switch
+            }
+            _ => return Ok(None),
+        };
+        let effective = translate(switch.previous, rate, info.clone(), height);
+        Ok(match effective {
+            Effective::Active(_, translated) if translated == *info => None,
+            effective => Some(effective),
+        })
+    }
+
+    /// The rate in effect at HEIGHT, if an adaptive rate is set.
+    pub(crate) fn rate_at(&self, storage: &dyn Storage, height: u64) -> StdResult<Option<Rate>> {
+        Ok(match self.config.may_load(storage)? {
+            Some(config) => {
+                let window = config.advance(self.window.may_load(storage)?, height);
+                Some(Rate::PerBlock(window.limit))
+            }
+            None => None,
+        })
+    }
+
+    /// Moves to the window containing HEIGHT. If the limit has
+    /// changed since it was last reported, returns a
+    /// `rate_limit_adapted` event with `previous` and `current`
+    /// per-block limits if REPORT is set, and otherwise keeps the
+    /// change to be reported by a later call.
+    pub(crate) fn update(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        report: bool,
+    ) -> StdResult<Option<Event>> {
+        let config = match self.config.may_load(storage)? {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+        if self.switching_at(storage, height)?.is_some() {
+            return Ok(None);
+        }
+        let previous = self.window.may_load(storage)?;
+        let mut window = config.advance(previous.clone(), height);
+        let reported = previous.as_ref().map(|p| p.unreported.unwrap_or(p.limit));
+        let changed = reported.filter(|reported| *reported != window.limit);
+        let event = match changed {
+            Some(reported) if report => Some(
+                Event::new("rate_limit_adapted")
+                    .add_attribute("previous", reported.to_string())
+                    .add_attribute("current", window.limit.to_string()),
+            ),
+            _ => None,
+        };
+        window.unreported = changed.filter(|_| !report);
+        if previous.as_ref() != Some(&window) {
+            self.window.save(storage, &window)?;
+        }
+        Ok(event)
+    }
+
+    /// Counts UNITS limited at HEIGHT towards the current window.
+    /// Expects `update` to have been called at HEIGHT.
+    pub(crate) fn record(&self, storage: &mut dyn Storage, units: u64) -> StdResult<()> {
+        if let Some(mut window) = self.window.may_load(storage)? {
+            window.total = window.total.saturating_add(units);
+            self.window.save(storage, &window)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables adaptive rates, stored under CONFIG_KEY, WINDOW_KEY
+    /// and SWITCH_KEY. While an adaptive rate is set with
+    /// `set_adaptive_rate`, it replaces the rate limit for every key.
+    pub const fn with_adaptive_rate(
+        mut self,
+        config_key: &'a str,
+        window_key: &'a str,
+        switch_key: &'a str,
+    ) -> Self {
+        self.adaptive = Some(Adaptive::new(config_key, window_key, switch_key));
+        self
+    }
+
+    /// Sets the adaptive rate, or removes it if ADAPTIVE_RATE is
+    /// `None`, returning to the configured rate limit. Like
+    /// `update_rate`, this takes effect in the next block. Keys
+    /// moving between a `PerBlock` and a `Blocks` rate finish their
+    /// open window first, as with boosts. The limit starts over at
+    /// `max` if the adaptive rate was removed, and is otherwise kept
+    /// within the new bounds. If the window length changes, units
+    /// counted so far carry over to the new window containing the
+    /// current one's start. Errors if adaptive rates are not enabled.
+    pub fn set_adaptive_rate(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        adaptive_rate: Option<&AdaptiveRate>,
+    ) -> StdResult<()> {
+        let adaptive = self.adaptive()?;
+        let switch = |storage: &mut dyn Storage| -> StdResult<()> {
+            let height = env.block.height;
+            let previous = self.current_rate(storage, height)?;
+            adaptive
+                .switch
+                .save(storage, &AdaptiveSwitch { height, previous })
+        };
+        match adaptive_rate {
+            Some(config) if config.window_blocks == 0 => Err(StdError::generic_err(
+                "adaptive rate window must be non-zero",
+            )),
+            Some(config) if config.min == 0 || config.min > config.max => Err(
+                StdError::generic_err("adaptive rate bounds must be non-zero and ordered"),
+            ),
+            Some(config) if config.backoff >= Decimal::one() => Err(StdError::generic_err(
+                "adaptive rate backoff must be less than one",
+            )),
+            Some(config) => {
+                switch(storage)?;
+                if let Some(mut window) = adaptive.window.may_load(storage)? {
+                    let aligned = window.window_start - window.window_start % config.window_blocks;
+                    if aligned != window.window_start {
+                        window.window_start = aligned;
+                        adaptive.window.save(storage, &window)?;
+                    }
+                }
+                adaptive.config.save(storage, config)
+            }
+            None => {
+                switch(storage)?;
+                adaptive.config.remove(storage);
+                adaptive.window.remove(storage);
+                Ok(())
+            }
+        }
+    }
+
+    /// Gets the adaptive rate's configuration and the rate in effect
+    /// at HEIGHT. Errors if adaptive rates are not enabled.
+    pub fn query_adaptive_rate(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+    ) -> StdResult<AdaptiveRateState> {
+        let adaptive = self.adaptive()?;
+        Ok(AdaptiveRateState {
+            adaptive_rate: adaptive.config(storage)?,
+            rate: adaptive.rate_at(storage, height)?,
+        })
+    }
+
+    fn adaptive(&self) -> StdResult<&Adaptive<'a>> {
+        self.adaptive
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not have adaptive rates"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::RateLimitError;
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r").with_adaptive_rate("ar", "arw", "ars");
+
+    fn at(height: u64) -> Env {
+        let mut env = mock_env();
+        env.block.height = height;
+        env
+    }
+
+    fn config() -> AdaptiveRate {
+        AdaptiveRate {
+            min: 1,
+            max: 4,
+            window_blocks: 10,
+            target: 3,
+            increase: 1,
+            backoff: Decimal::percent(50),
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let config = config();
+        let window = |limit, window_start, total| AdaptiveWindow {
+            limit,
+            window_start,
+            total,
+            unreported: None,
+        };
+        assert_eq!(config.advance(None, 15), window(4, 10, 0));
+        // Same window.
+        assert_eq!(config.advance(Some(window(2, 10, 9)), 19), window(2, 10, 9));
+        // Surge.
+        assert_eq!(config.advance(Some(window(4, 10, 4)), 20), window(2, 20, 0));
+        assert_eq!(config.advance(Some(window(1, 10, 4)), 20), window(1, 20, 0));
+        // Quiet.
+        assert_eq!(config.advance(Some(window(2, 10, 3)), 20), window(3, 20, 0));
+        assert_eq!(config.advance(Some(window(4, 10, 0)), 20), window(4, 20, 0));
+        // Surge, then two empty windows.
+        assert_eq!(config.advance(Some(window(4, 10, 5)), 40), window(4, 40, 0));
+        assert_eq!(config.advance(Some(window(2, 10, 5)), 30), window(2, 30, 0));
+        // Bounds changed since the last window.
+        assert_eq!(config.advance(Some(window(9, 10, 0)), 15), window(4, 10, 0));
+        // Windows not aligned to the config are treated as the last
+        // one, whichever side of the current window they are on.
+        let config = AdaptiveRate {
+            window_blocks: 30,
+            ..config
+        };
+        assert_eq!(
+            config.advance(Some(window(2, 100, 0)), 101),
+            window(3, 90, 0)
+        );
+        assert_eq!(
+            config.advance(Some(window(2, 20, 0)), 101),
+            window(4, 90, 0)
+        );
+    }
+
+    #[test]
+    fn test_reconfigure_window() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 101;
+        LIMITER.init(storage, &Rate::Blocks(10)).unwrap();
+        LIMITER
+            .set_adaptive_rate(storage, &at(100), Some(&config()))
+            .unwrap();
+        for key in ["a", "b", "c", "d"] {
+            LIMITER.limit(storage, &env, key).unwrap();
+        }
+
+        // Units counted in the window starting at 100 carry over to
+        // the one starting at 90.
+        LIMITER
+            .set_adaptive_rate(
+                storage,
+                &env,
+                Some(&AdaptiveRate {
+                    window_blocks: 30,
+                    ..config()
+                }),
+            )
+            .unwrap();
+        LIMITER.limit(storage, &env, "e").unwrap();
+        env.block.height = 119;
+        LIMITER.limit(storage, &env, "e").unwrap();
+        assert_eq!(
+            LIMITER.query_adaptive_rate(storage, 119).unwrap().rate,
+            Some(Rate::PerBlock(4))
+        );
+        env.block.height = 120;
+        let events = LIMITER.limit_with_event(storage, &env, "e").unwrap();
+        assert_eq!(
+            events[0],
+            Event::new("rate_limit_adapted")
+                .add_attribute("previous", "4")
+                .add_attribute("current", "2")
+        );
+
+        // Shrinking the window realigns it too.
+        LIMITER
+            .set_adaptive_rate(storage, &env, Some(&config()))
+            .unwrap();
+        env.block.height = 125;
+        LIMITER.limit(storage, &env, "e").unwrap();
+        env.block.height = 130;
+        LIMITER.limit(storage, &env, "e").unwrap();
+        assert_eq!(
+            LIMITER.query_adaptive_rate(storage, 130).unwrap().rate,
+            Some(Rate::PerBlock(3))
+        );
+    }
+
+    #[test]
+    fn test_adaptive_rate() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 99;
+        LIMITER.init(storage, &Rate::Blocks(10)).unwrap();
+
+        // Without a config, the rate limit applies.
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        assert_eq!(
+            LIMITER.query_adaptive_rate(storage, 99).unwrap(),
+            AdaptiveRateState {
+                adaptive_rate: None,
+                rate: None,
+            }
+        );
+
+        // The adaptive rate applies from the next block, once a has
+        // finished its window.
+        LIMITER
+            .set_adaptive_rate(storage, &env, Some(&config()))
+            .unwrap();
+        env.block.height = 100;
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        let events = LIMITER.limit_with_event(storage, &env, "b").unwrap();
+        assert_eq!(events.len(), 1);
+        for key in ["b", "b", "b", "c"] {
+            LIMITER.limit(storage, &env, key).unwrap();
+        }
+        assert!(matches!(
+            LIMITER.limit(storage, &env, "b").unwrap_err(),
+            RateLimitError::Limited { .. }
+        ));
+
+        // Five units over a target of three halves the limit.
+        env.block.height = 110;
+        let events = LIMITER.limit_with_event(storage, &env, "b").unwrap();
+        assert_eq!(
+            events[0],
+            Event::new("rate_limit_adapted")
+                .add_attribute("previous", "4")
+                .add_attribute("current", "2")
+        );
+        LIMITER.limit(storage, &env, "b").unwrap();
+        LIMITER.limit(storage, &env, "b").unwrap_err();
+        assert_eq!(
+            LIMITER.query_adaptive_rate(storage, 119).unwrap().rate,
+            Some(Rate::PerBlock(2))
+        );
+        assert_eq!(
+            LIMITER.query_adaptive_rate(storage, 120).unwrap().rate,
+            Some(Rate::PerBlock(3))
+        );
+        assert_eq!(
+            LIMITER
+                .query_key_state(storage, &env, "b".to_string())
+                .unwrap()
+                .usage
+                .remaining,
+            0
+        );
+
+        // Removing the adaptive rate restores the rate limit from
+        // the next block.
+        LIMITER.set_adaptive_rate(storage, &env, None).unwrap();
+        LIMITER.limit(storage, &env, "b").unwrap_err();
+        env.block.height = 111;
+        LIMITER.limit(storage, &env, "b").unwrap();
+        LIMITER.limit(storage, &env, "b").unwrap_err();
+    }
+
+    #[test]
+    fn test_switch_kind() {
+        let storage = &mut MockStorage::default();
+        LIMITER.init(storage, &Rate::Blocks(10)).unwrap();
+        LIMITER
+            .set_adaptive_rate(
+                storage,
+                &at(99),
+                Some(&AdaptiveRate {
+                    max: 100,
+                    ..config()
+                }),
+            )
+            .unwrap();
+        LIMITER.limit_many(storage, &at(100), &[("a", 50)]).unwrap();
+        LIMITER.set_adaptive_rate(storage, &at(100), None).unwrap();
+
+        // a's window under the adaptive rate ended with block 100, so
+        // it starts over under the rate limit.
+        LIMITER.limit(storage, &at(101), "a").unwrap();
+        assert_eq!(
+            LIMITER.limit(storage, &at(101), "a").unwrap_err(),
+            RateLimitError::Limited {
+                key: "a".to_string(),
+                blocks_remaining: 10,
+            }
+        );
+    }
+
+    #[test]
+    fn test_deferred_event() {
+        let storage = &mut MockStorage::default();
+        LIMITER.init(storage, &Rate::Blocks(10)).unwrap();
+        LIMITER
+            .set_adaptive_rate(storage, &at(99), Some(&config()))
+            .unwrap();
+        LIMITER.limit_many(storage, &at(100), &[("a", 4)]).unwrap();
+        LIMITER.limit(storage, &at(110), "a").unwrap();
+
+        // The change made by `limit` is reported once, by the next
+        // call that returns events.
+        let events = LIMITER.limit_with_event(storage, &at(111), "b").unwrap();
+        assert_eq!(
+            events[0],
+            Event::new("rate_limit_adapted")
+                .add_attribute("previous", "4")
+                .add_attribute("current", "2")
+        );
+        let events = LIMITER.limit_with_event(storage, &at(112), "b").unwrap();
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_rejected_batch() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::Blocks(10)).unwrap();
+        LIMITER
+            .set_adaptive_rate(storage, &at(99), Some(&config()))
+            .unwrap();
+        LIMITER.limit_many(storage, &env, &[("a", 4)]).unwrap();
+
+        // A rejected batch does not move the window, and the next
+        // call reports the limit halving.
+        env.block.height = 110;
+        LIMITER.limit_many(storage, &env, &[("a", 3)]).unwrap_err();
+        let events = LIMITER.limit_with_event(storage, &env, "b").unwrap();
+        assert_eq!(
+            events[0],
+            Event::new("rate_limit_adapted")
+                .add_attribute("previous", "4")
+                .add_attribute("current", "2")
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let storage = &mut MockStorage::default();
+        for config in [
+            AdaptiveRate {
+                window_blocks: 0,
+                ..config()
+            },
+            AdaptiveRate { min: 0, ..config() },
+            AdaptiveRate { min: 5, ..config() },
+            AdaptiveRate {
+                backoff: Decimal::one(),
+                ..config()
+            },
+        ] {
+            LIMITER
+                .set_adaptive_rate(storage, &mock_env(), Some(&config))
+                .unwrap_err();
+        }
+        RateLimiter::new("rl", "r")
+            .query_adaptive_rate(storage, 1)
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,335 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{BlockInfo, Env, Order, StdError, StdResult, Storage};
+use cw_storage_plus::{Bound, Map};
+use cw_utils::Expiration;
+
+use crate::update::Effective;
+use crate::{Rate, RateInfo, RateLimiter, RateUsage};
+
+/// A temporary rate for a single key, in place of the rate limit.
+#[cw_serde]
+pub struct Boost {
+    pub rate: Rate,
+    /// The first height at which the boost applies.
+    pub start_height: u64,
+    /// When the key goes back to the rate limit.
+    pub expires_at: Expiration,
+}
+
+#[cw_serde]
+pub struct BoostedKey {
+    pub key: String,
+    pub boost: Boost,
+}
+
+pub(crate) struct Boosts<'a, 'b> {
+    boosts: Map<'a, &'b str, Boost>,
+}
+
+// How the boost for a key affects its rate.
+pub(crate) enum Boosted {
+    // The key is boosted.
+    Active(Effective),
+    // The key is limited under the rate limit, with INFO translated
+    // from any boost that has since expired.
+    Base(Option<RateInfo>),
+}
+
+// Translates INFO, recorded under FROM, to TO at HEIGHT. Windows are
+// kept as-is if both rates are of the same kind, as they count units
+// the same way. Otherwise, an open window must end before the key
+// starts over under TO.
+pub(crate) fn translate(from: Rate, to: Rate, info: RateInfo, height: u64) -> Effective {
+    let same_kind = matches!(
+        (from, to),
+        (Rate::PerBlock(_), Rate::PerBlock(_)) | (Rate::Blocks(_), Rate::Blocks(_))
+    );
+    if same_kind {
+        return Effective::Active(to, info);
+    }
+    let usage = RateUsage::new(from, &info, height);
+    if usage.used > 0 && usage.window_end > height {
+        Effective::BlockedUntil(usage.window_end)
+    } else {
+        Effective::Active(to, RateInfo::default())
+    }
+}
+
+impl<'a, 'b> Boosts<'a, 'b> {
+    pub(crate) const fn new(boosts_key: &'a str) -> Self {
+        Self {
+            boosts: Map::new(boosts_key),
+        }
+    }
+
+    /// Applies KEY's boost, if any, to INFO, its state under RATE.
+    pub(crate) fn apply(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        key: &str,
+        rate: Rate,
+        info: Option<RateInfo>,
+    ) -> StdResult<Boosted> {
+        let boost = match self.boosts.may_load(storage, key)? {
+            Some(boost) if boost.start_height <= block.height => boost,
+            _ => return Ok(Boosted::Base(info)),
+        };
+        let info = match info {
+            Some(info) => info,
+            None if boost.expires_at.is_expired(block) => return Ok(Boosted::Base(None)),
+            None => {
+                return Ok(Boosted::Active(Effective::Active(
+                    boost.rate,
+                    RateInfo::default(),
+                )))
+            }
+        };
+        let under_boost = info.last_updated_height >= boost.start_height;
+        Ok(match (boost.expires_at.is_expired(block), under_boost) {
+            (false, true) => Boosted::Active(Effective::Active(boost.rate, info)),
+            (false, false) => Boosted::Active(translate(rate, boost.rate, info, block.height)),
+            (true, true) => match translate(boost.rate, rate, info, block.height) {
+                Effective::Active(_, info) => Boosted::Base(Some(info)),
+                blocked => Boosted::Active(blocked),
+            },
+            (true, false) => Boosted::Base(Some(info)),
+        })
+    }
+
+    /// Removes KEY's boost if it has expired.
+    pub(crate) fn remove_expired(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        key: &str,
+    ) -> StdResult<()> {
+        if let Some(boost) = self.boosts.may_load(storage, key)? {
+            if boost.expires_at.is_expired(block) {
+                self.boosts.remove(storage, key);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables per-key boosts, stored under BOOSTS_KEY.
+    pub const fn with_boosts(mut self, boosts_key: &'a str) -> Self {
+        self.boosts = Some(Boosts::new(boosts_key));
+        self
+    }
+
+    /// Limits KEY at RATE instead of the rate limit from the next
+    /// block until EXPIRES_AT, after which it goes back to the rate
+    /// limit. Replaces any boost the key already has, keeping its
+    /// start height if it has started. Callers are responsible for
+    /// checking that the sender is authorized to do this. Errors if
+    /// boosts are not enabled or EXPIRES_AT has passed.
+    pub fn boost(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        key: &str,
+        rate: Rate,
+        expires_at: Expiration,
+    ) -> StdResult<()> {
+        let boosts = self.boosts()?;
+        if expires_at.is_expired(&env.block) {
+            return Err(StdError::generic_err("boost has already expired"));
+        }
+        let start_height = match boosts.boosts.may_load(storage, key)? {
+            Some(boost)
+                if boost.start_height <= env.block.height
+                    && !boost.expires_at.is_expired(&env.block) =>
+            {
+                boost.start_height
+            }
+            _ => env.block.height + 1,
+        };
+        boosts.boosts.save(
+            storage,
+            key,
+            &Boost {
+                rate,
+                start_height,
+                expires_at,
+            },
+        )
+    }
+
+    /// Removes KEY's boost, if it has one. Errors if boosts are not
+    /// enabled.
+    pub fn remove_boost(&self, storage: &mut dyn Storage, key: &str) -> StdResult<()> {
+        self.boosts()?.boosts.remove(storage, key);
+        Ok(())
+    }
+
+    /// Gets KEY's boost, or `None` if it does not have one that is
+    /// pending or in effect. Errors if boosts are not enabled.
+    pub fn query_boost(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        key: &str,
+    ) -> StdResult<Option<Boost>> {
+        Ok(self
+            .boosts()?
+            .boosts
+            .may_load(storage, key)?
+            .filter(|boost| !boost.expires_at.is_expired(&env.block)))
+    }
+
+    /// Lists keys with a boost that is pending or in effect, in
+    /// ascending order of key. Errors if boosts are not enabled.
+    pub fn query_boosts(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        start_after: Option<String>,
+        limit: usize,
+    ) -> StdResult<Vec<BoostedKey>> {
+        self.boosts()?
+            .boosts
+            .range(
+                storage,
+                start_after.map(|s| Bound::ExclusiveRaw(s.into_bytes())),
+                None,
+                Order::Ascending,
+            )
+            .filter(|item| {
+                item.as_ref()
+                    .map_or(true, |(_, boost)| !boost.expires_at.is_expired(&env.block))
+            })
+            .take(limit)
+            .map(|item| item.map(|(key, boost)| BoostedKey { key, boost }))
+            .collect()
+    }
+
+    fn boosts(&self) -> StdResult<&Boosts<'a, 'b>> {
+        self.boosts
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not have boosts"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::RateLimitError;
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r").with_boosts("b");
+
+    #[test]
+    fn test_boost() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::PerBlock(1)).unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap();
+
+        LIMITER
+            .boost(
+                storage,
+                &env,
+                "a",
+                Rate::PerBlock(3),
+                Expiration::AtHeight(102),
+            )
+            .unwrap();
+        // Applies from the next block.
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        env.block.height = 101;
+        for _ in 0..3 {
+            LIMITER.limit(storage, &env, "a").unwrap();
+        }
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        LIMITER.limit(storage, &env, "b").unwrap();
+        LIMITER.limit(storage, &env, "b").unwrap_err();
+        assert_eq!(
+            LIMITER.query_boosts(storage, &env, None, 10).unwrap(),
+            vec![BoostedKey {
+                key: "a".to_string(),
+                boost: Boost {
+                    rate: Rate::PerBlock(3),
+                    start_height: 101,
+                    expires_at: Expiration::AtHeight(102),
+                }
+            }]
+        );
+
+        // Expired, and cleaned up by `limit`.
+        env.block.height = 102;
+        assert_eq!(LIMITER.query_boost(storage, &env, "a").unwrap(), None);
+        assert_eq!(
+            LIMITER.query_boosts(storage, &env, None, 10).unwrap(),
+            vec![]
+        );
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        assert!(LIMITER
+            .boosts()
+            .unwrap()
+            .boosts
+            .may_load(storage, "a")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_boost_other_kind() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::Blocks(10)).unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap();
+
+        let expires_at = Expiration::AtTime(env.block.time.plus_seconds(60));
+        LIMITER
+            .boost(storage, &env, "a", Rate::PerBlock(2), expires_at)
+            .unwrap();
+        // The window opened under the rate limit must end first.
+        env.block.height = 105;
+        assert_eq!(
+            LIMITER.limit(storage, &env, "a").unwrap_err(),
+            RateLimitError::Limited {
+                key: "a".to_string(),
+                blocks_remaining: 5
+            }
+        );
+        env.block.height = 110;
+        LIMITER
+            .limit_many(storage, &env, &[("a", 1), ("a", 1)])
+            .unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+
+        // Once expired, the key starts over under the rate limit.
+        env.block.height = 111;
+        env.block.time = env.block.time.plus_seconds(60);
+        LIMITER.limit_many(storage, &env, &[("a", 1)]).unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        env.block.height = 121;
+        LIMITER.limit(storage, &env, "a").unwrap();
+    }
+
+    #[test]
+    fn test_boost_errors() {
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        LIMITER
+            .boost(
+                storage,
+                &env,
+                "a",
+                Rate::PerBlock(2),
+                Expiration::AtHeight(env.block.height),
+            )
+            .unwrap_err();
+        RateLimiter::new("rl", "r")
+            .boost(storage, &env, "a", Rate::PerBlock(2), Expiration::Never {})
+            .unwrap_err();
+    }
+}
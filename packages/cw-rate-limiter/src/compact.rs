@@ -0,0 +1,274 @@
+use cosmwasm_std::{Env, StdError, StdResult, Storage};
+use cw_storage_plus::{Item, Map};
+
+use crate::{Rate, RateInfo, RateLimitError};
+
+const RATE_LEN: usize = 9;
+const RATE_INFO_LEN: usize = 16;
+
+/// A rate limiter with the same semantics as `RateLimiter::limit`,
+/// for contracts whose hot path is only this check. Rather than
+/// JSON, the rate is stored in 9 bytes and each key's state in 16,
+/// which is cheaper to read and much cheaper to write.
+///
+/// Created with `new_fixed`, the rate is a compile-time constant and
+/// `limit` does a single storage read. None of `RateLimiter`'s
+/// optional features are supported, and its storage layout is not
+/// compatible with this one.
+pub struct CompactRateLimiter<'a, 'b> {
+    rate: CompactRate<'a>,
+    rates: Map<'a, &'b str, RateInfo>,
+}
+
+enum CompactRate<'a> {
+    Stored(Item<'a, Rate>),
+    Fixed(Rate),
+}
+
+impl<'a> CompactRateLimiter<'a, '_> {
+    /// Creates a limiter whose rate is stored under RATE_LIMIT_KEY
+    /// and set with `init`.
+    pub const fn new(rate_limit_key: &'a str, rates_key: &'a str) -> Self {
+        Self {
+            rate: CompactRate::Stored(Item::new(rate_limit_key)),
+            rates: Map::new(rates_key),
+        }
+    }
+
+    /// Creates a limiter that always limits at RATE. Changing the rate
+    /// requires a code migration.
+    pub const fn new_fixed(rate: Rate, rates_key: &'a str) -> Self {
+        Self {
+            rate: CompactRate::Fixed(rate),
+            rates: Map::new(rates_key),
+        }
+    }
+
+    /// Sets the rate limit. Errors if the rate is fixed.
+    pub fn init(&self, storage: &mut dyn Storage, rate_limit: &Rate) -> StdResult<()> {
+        match &self.rate {
+            CompactRate::Stored(item) => {
+                storage.set(item.as_slice(), &encode_rate(rate_limit));
+                Ok(())
+            }
+            CompactRate::Fixed(_) => Err(StdError::generic_err("rate limit is fixed")),
+        }
+    }
+
+    pub fn limit(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        key: &str,
+    ) -> Result<(), RateLimitError> {
+        let rate = self.query_limit(storage)?;
+        let path = self.rates.key(key);
+        let info = match storage.get(&path) {
+            Some(bytes) => decode_rate_info(&bytes)?,
+            None => RateInfo::default(),
+        };
+        let info = rate
+            .charge(&info, env.block.height, 1)
+            .map_err(|blocks_remaining| RateLimitError::Limited {
+                key: key.to_string(),
+                blocks_remaining,
+            })?;
+        storage.set(&path, &encode_rate_info(&info));
+        Ok(())
+    }
+
+    pub fn query_limit(&self, storage: &dyn Storage) -> StdResult<Rate> {
+        match &self.rate {
+            CompactRate::Stored(item) => match storage.get(item.as_slice()) {
+                Some(bytes) => decode_rate(&bytes),
+                None => Err(StdError::not_found("rate limit")),
+            },
+            CompactRate::Fixed(rate) => Ok(*rate),
+        }
+    }
+
+    pub fn query_rate_info(&self, storage: &dyn Storage, key: &str) -> StdResult<Option<RateInfo>> {
+        storage
+            .get(&self.rates.key(key))
+            .map(|bytes| decode_rate_info(&bytes))
+            .transpose()
+    }
+}
+
+fn encode_rate(rate: &Rate) -> [u8; RATE_LEN] {
+    let (tag, value) = match rate {
+        Rate::PerBlock(n) => (0, n),
+        Rate::Blocks(n) => (1, n),
+    };
+    let mut bytes = [tag; RATE_LEN];
+    bytes[1..].copy_from_slice(&value.to_be_bytes());
+    bytes
+}
+
+fn decode_rate(bytes: &[u8]) -> StdResult<Rate> {
+    let bytes: &[u8; RATE_LEN] = bytes
+        .try_into()
+        .map_err(|_| StdError::parse_err("Rate", "invalid length"))?;
+    let value = u64::from_be_bytes(bytes[1..].try_into().unwrap());
+    match bytes[0] {
+        0 => Ok(Rate::PerBlock(value)),
+        1 => Ok(Rate::Blocks(value)),
+        _ => Err(StdError::parse_err("Rate", "invalid tag")),
+    }
+}
+
+fn encode_rate_info(info: &RateInfo) -> [u8; RATE_INFO_LEN] {
+    let mut bytes = [0; RATE_INFO_LEN];
+    bytes[..8].copy_from_slice(&info.last_updated_height.to_be_bytes());
+    bytes[8..].copy_from_slice(&info.this_block.to_be_bytes());
+    bytes
+}
+
+fn decode_rate_info(bytes: &[u8]) -> StdResult<RateInfo> {
+    if bytes.len() != RATE_INFO_LEN {
+        return Err(StdError::parse_err("RateInfo", "invalid length"));
+    }
+    Ok(RateInfo {
+        last_updated_height: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+        this_block: u64::from_be_bytes(bytes[8..].try_into().unwrap()),
+        credit: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+    use cosmwasm_std::{Order, Record};
+
+    use crate::RateLimiter;
+
+    use super::*;
+
+    // Charges gas for storage access using the Cosmos SDK's default
+    // KV store gas config, which wasmd uses for contract storage.
+    #[derive(Default)]
+    struct GasMeter {
+        storage: MockStorage,
+        gas: Cell<u64>,
+        reads: Cell<u64>,
+    }
+
+    impl GasMeter {
+        fn take(&mut self) -> (u64, u64) {
+            (self.gas.replace(0), self.reads.replace(0))
+        }
+    }
+
+    impl Storage for GasMeter {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            let value = self.storage.get(key);
+            let len = value.as_ref().map_or(0, |v| v.len());
+            self.gas
+                .set(self.gas.get() + 1000 + 3 * (key.len() + len) as u64);
+            self.reads.set(self.reads.get() + 1);
+            value
+        }
+
+        fn range<'a>(
+            &'a self,
+            start: Option<&[u8]>,
+            end: Option<&[u8]>,
+            order: Order,
+        ) -> Box<dyn Iterator<Item = Record> + 'a> {
+            self.storage.range(start, end, order)
+        }
+
+        fn set(&mut self, key: &[u8], value: &[u8]) {
+            self.gas
+                .set(self.gas.get() + 2000 + 30 * (key.len() + value.len()) as u64);
+            self.storage.set(key, value)
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            self.gas.set(self.gas.get() + 1000);
+            self.storage.remove(key)
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        for rate in [Rate::PerBlock(0), Rate::Blocks(!0), Rate::PerBlock(12)] {
+            assert_eq!(decode_rate(&encode_rate(&rate)).unwrap(), rate);
+        }
+        let info = RateInfo {
+            last_updated_height: 12345678,
+            this_block: 3,
+            credit: 0,
+        };
+        assert_eq!(decode_rate_info(&encode_rate_info(&info)).unwrap(), info);
+        decode_rate(&[2; RATE_LEN]).unwrap_err();
+        decode_rate_info(&[0; 15]).unwrap_err();
+    }
+
+    #[test]
+    fn test_same_as_rate_limiter() {
+        let compact = CompactRateLimiter::new("rl", "r");
+        let fixed = CompactRateLimiter::new_fixed(Rate::PerBlock(2), "r");
+        let limiter = RateLimiter::new("rl", "r");
+        let (cs, fs, ls) = &mut (
+            MockStorage::default(),
+            MockStorage::default(),
+            MockStorage::default(),
+        );
+        let mut env = mock_env();
+        compact.init(cs, &Rate::PerBlock(2)).unwrap();
+        fixed.init(fs, &Rate::PerBlock(2)).unwrap_err();
+        limiter.init(ls, &Rate::PerBlock(2)).unwrap();
+
+        for (blocks, key) in [(0, "a"), (0, "a"), (0, "a"), (0, "b"), (1, "a"), (3, "a")] {
+            env.block.height += blocks;
+            let expected = limiter.limit(ls, &env, key);
+            assert_eq!(compact.limit(cs, &env, key), expected);
+            assert_eq!(fixed.limit(fs, &env, key), expected);
+            assert_eq!(
+                compact.query_rate_info(cs, key).unwrap(),
+                limiter.query_rate_info(ls, key).unwrap()
+            );
+        }
+    }
+
+    // Compares the gas used by a call to `limit` with today's JSON
+    // layout against the compact ones.
+    #[test]
+    fn bench_gas() {
+        let height = 12_345_678;
+        let key = "juno1qwertyuiopasdfghjklzxcvbnm1234567890qwertyuiopasdfghjk";
+        let rate = Rate::PerBlock(100);
+
+        let mut json = GasMeter::default();
+        let limiter = RateLimiter::new("rate_limit", "sender");
+        limiter.init(&mut json, &rate).unwrap();
+        let mut env = mock_env();
+        env.block.height = height;
+        limiter.limit(&mut json, &env, key).unwrap();
+        json.take();
+        limiter.limit(&mut json, &env, key).unwrap();
+        let (json_gas, json_reads) = json.take();
+
+        let mut compact = GasMeter::default();
+        let limiter = CompactRateLimiter::new("rate_limit", "sender");
+        limiter.init(&mut compact, &rate).unwrap();
+        limiter.limit(&mut compact, &env, key).unwrap();
+        compact.take();
+        limiter.limit(&mut compact, &env, key).unwrap();
+        let (compact_gas, compact_reads) = compact.take();
+
+        let mut fixed = GasMeter::default();
+        let limiter = CompactRateLimiter::new_fixed(rate, "sender");
+        limiter.limit(&mut fixed, &env, key).unwrap();
+        fixed.take();
+        limiter.limit(&mut fixed, &env, key).unwrap();
+        let (fixed_gas, fixed_reads) = fixed.take();
+
+        assert_eq!((json_reads, compact_reads, fixed_reads), (2, 2, 1));
+        assert!(compact_gas < json_gas);
+        assert!(fixed_gas < compact_gas);
+    }
+}
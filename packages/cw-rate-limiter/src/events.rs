@@ -0,0 +1,149 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::Event;
+
+use crate::{Rate, RateInfo};
+
+/// A key's use of its quota at some height.
+#[cw_serde]
+pub struct RateUsage {
+    /// Units used in the current window.
+    pub used: u64,
+    /// Units that may still be used in the current window, including
+    /// any net-flow credit.
+    pub remaining: u64,
+    /// First height at which the key's full quota is available
+    /// again. Equal to the current height if nothing has been used.
+    pub window_end: u64,
+}
+
+impl RateUsage {
+    /// Computes the usage of a key with state INFO at HEIGHT under
+    /// RATE.
+    pub fn new(rate: Rate, info: &RateInfo, height: u64) -> Self {
+        match rate {
+            Rate::PerBlock(limit) => {
+                let (used, credit) = info.this_window(height);
+                Self {
+                    used,
+                    remaining: limit.saturating_sub(used).saturating_add(credit),
+                    window_end: if used == 0 { height } else { height + 1 },
+                }
+            }
+            Rate::Blocks(blocks) => {
+                let window_end = info.blocks_window_end(blocks);
+                if window_end > height {
+                    Self {
+                        used: info.this_block.max(1),
+                        remaining: 0,
+                        window_end,
+                    }
+                } else {
+                    Self {
+                        used: 0,
+                        remaining: 1,
+                        window_end: height,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Creates a `rate_limit` event with `key`, `used`, `remaining`
+    /// and `window_end` attributes. Note that the chain prefixes
+    /// event types emitted by contracts with `wasm-`.
+    pub fn event(&self, key: &str) -> Event {
+        Event::new("rate_limit")
+            .add_attribute("key", key)
+            .add_attribute("used", self.used.to_string())
+            .add_attribute("remaining", self.remaining.to_string())
+            .add_attribute("window_end", self.window_end.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::RateLimiter;
+
+    use super::*;
+
+    #[test]
+    fn test_limit_with_event() {
+        let limiter = RateLimiter::new("rl", "r");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        let height = env.block.height;
+        limiter.init(storage, &Rate::PerBlock(3)).unwrap();
+
+        limiter.limit(storage, &env, "a").unwrap();
+        let events = limiter.limit_with_event(storage, &env, "a").unwrap();
+        assert_eq!(
+            events,
+            vec![Event::new("rate_limit")
+                .add_attribute("key", "a")
+                .add_attribute("used", "2")
+                .add_attribute("remaining", "1")
+                .add_attribute("window_end", (height + 1).to_string())]
+        );
+    }
+
+    #[test]
+    fn test_usage() {
+        let info = RateInfo {
+            last_updated_height: 10,
+            this_block: 2,
+            credit: 0,
+        };
+        assert_eq!(
+            RateUsage::new(Rate::PerBlock(5), &info, 10),
+            RateUsage {
+                used: 2,
+                remaining: 3,
+                window_end: 11
+            }
+        );
+        assert_eq!(
+            RateUsage::new(Rate::PerBlock(5), &info, 11),
+            RateUsage {
+                used: 0,
+                remaining: 5,
+                window_end: 11
+            }
+        );
+        let info = RateInfo {
+            last_updated_height: 10,
+            this_block: 5,
+            credit: 2,
+        };
+        assert_eq!(
+            RateUsage::new(Rate::PerBlock(5), &info, 10),
+            RateUsage {
+                used: 5,
+                remaining: 2,
+                window_end: 11
+            }
+        );
+        let info = RateInfo {
+            last_updated_height: 10,
+            this_block: 1,
+            credit: 0,
+        };
+        assert_eq!(
+            RateUsage::new(Rate::Blocks(4), &info, 12),
+            RateUsage {
+                used: 1,
+                remaining: 0,
+                window_end: 14
+            }
+        );
+        assert_eq!(
+            RateUsage::new(Rate::Blocks(4), &info, 14),
+            RateUsage {
+                used: 0,
+                remaining: 1,
+                window_end: 14
+            }
+        );
+    }
+}
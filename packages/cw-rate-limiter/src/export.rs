@@ -0,0 +1,258 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Env, Order, StdError, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+use crate::{RateInfo, RateLimiter, RateLimiterConfig};
+
+#[cw_serde]
+pub struct ExportedKey {
+    pub key: String,
+    pub info: RateInfo,
+}
+
+/// A page of the limiter's state, as returned by `export` and
+/// accepted by `import`.
+#[cw_serde]
+pub struct StateExport {
+    pub config: RateLimiterConfig,
+    /// Stored state for keys, in ascending order of key.
+    pub keys: Vec<ExportedKey>,
+    /// The key to start the next page after, or `None` if this is the
+    /// last page.
+    pub next: Option<String>,
+}
+
+impl RateLimiter<'_, '_> {
+    /// Exports the limiter's configuration and up to LIMIT keys after
+    /// START_AFTER. Usage counters, pending rate changes, and the
+    /// state of optional features such as boosts and vouchers are
+    /// not exported.
+    pub fn export(
+        &self,
+        storage: &dyn Storage,
+        start_after: Option<String>,
+        limit: usize,
+    ) -> StdResult<StateExport> {
+        let mut keys = self
+            .rates
+            .range(
+                storage,
+                start_after.map(|s| Bound::ExclusiveRaw(s.into_bytes())),
+                None,
+                Order::Ascending,
+            )
+            .take(limit + 1)
+            .map(|item| item.map(|(key, info)| ExportedKey { key, info }))
+            .collect::<StdResult<Vec<_>>>()?;
+        let next = if keys.len() > limit {
+            keys.truncate(limit);
+            keys.last().map(|k| k.key.clone())
+        } else {
+            None
+        };
+        Ok(StateExport {
+            config: self.query_config(storage)?,
+            keys,
+            next,
+        })
+    }
+
+    /// Imports a page of state exported by `export`, usually into a
+    /// fresh contract. If CONFIG is provided, it replaces the
+    /// limiter's configuration and should be imported with the first
+    /// page. Imported keys overwrite any stored state for them.
+    /// Errors if CONFIG sets an optional feature that is not enabled,
+    /// or if CONFIG is provided and the limiter already has keys, as
+    /// their state would then be read under the imported rate. To
+    /// change the rate of a limiter in use, see `update_rate`.
+    /// Callers are responsible for checking that the sender is
+    /// authorized to do this.
+    pub fn import(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        config: Option<&RateLimiterConfig>,
+        keys: &[ExportedKey],
+    ) -> StdResult<()> {
+        if let Some(config) = config {
+            if self
+                .rates
+                .keys_raw(storage, None, None, Order::Ascending)
+                .next()
+                .is_some()
+            {
+                return Err(StdError::generic_err(
+                    "can not import a config into a rate limiter that has keys",
+                ));
+            }
+            // If already initialized, the storage version is left
+            // as-is.
+            if self.rate_limit.may_load(storage)?.is_none() {
+                if let Some(version) = &self.version {
+                    version.init(storage)?;
+                }
+            }
+            self.save_rate_limit(storage, &config.rate_limit, env.block.height)?;
+            if self.new_keys.is_some() || config.new_key_limit.is_some() {
+                self.set_new_key_limit(storage, config.new_key_limit.as_ref())?;
+            }
+            if self.tripwire.is_some() || config.tripwire.is_some() {
+                self.set_tripwire(storage, config.tripwire.as_ref())?;
+            }
+            if self.adaptive.is_some() || config.adaptive_rate.is_some() {
+                self.set_adaptive_rate(storage, env, config.adaptive_rate.as_ref())?;
+            }
+            if self.global.is_some() || config.global_limit.is_some() {
+                self.set_global_limit(storage, config.global_limit.as_ref())?;
+            }
+            if self.grace.is_some() || config.grace_allowance.is_some() {
+                self.set_grace_allowance(storage, config.grace_allowance.as_ref())?;
+            }
+        }
+        let height = env.block.height;
+        let rate = self.current_rate(storage, height)?;
+        for ExportedKey { key, info } in keys {
+            self.save_rate_info(storage, rate, key, info, height)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::{NewKeyLimit, Rate, RateTransition};
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r")
+        .with_new_key_limit("nkl", "nkw")
+        .with_throttled_index("t");
+
+    #[test]
+    fn test_export_import() {
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        LIMITER.init(storage, &Rate::PerBlock(2)).unwrap();
+        LIMITER
+            .set_new_key_limit(
+                storage,
+                Some(&NewKeyLimit {
+                    max_new_keys: 10,
+                    window_blocks: 5,
+                }),
+            )
+            .unwrap();
+        for key in ["a", "b", "b", "c"] {
+            LIMITER.limit(storage, &env, key).unwrap();
+        }
+
+        let imported = &mut MockStorage::default();
+        let mut start_after = None;
+        let mut first = true;
+        loop {
+            let page = LIMITER.export(storage, start_after, 2).unwrap();
+            let config = first.then_some(&page.config);
+            LIMITER.import(imported, &env, config, &page.keys).unwrap();
+            first = false;
+            start_after = match page.next {
+                Some(next) => Some(next),
+                None => break,
+            };
+        }
+
+        assert_eq!(
+            LIMITER.export(imported, None, 10).unwrap(),
+            LIMITER.export(storage, None, 10).unwrap()
+        );
+        let page = LIMITER.export(imported, None, 2).unwrap();
+        assert_eq!(page.next, Some("b".to_string()));
+        assert_eq!(LIMITER.export(imported, page.next, 2).unwrap().next, None);
+        // Derived state is rebuilt.
+        assert_eq!(
+            LIMITER
+                .query_throttled(imported, &env, None, 10)
+                .unwrap()
+                .len(),
+            1
+        );
+        LIMITER.limit(imported, &env, "b").unwrap_err();
+        LIMITER.limit(imported, &env, "a").unwrap();
+    }
+
+    #[test]
+    fn test_import_config_with_keys() {
+        let limiter = LIMITER.with_storage_version("v", "vc", "vw");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        limiter.init(storage, &Rate::Blocks(30)).unwrap();
+        limiter.limit(storage, &env, "a").unwrap();
+        let page = limiter.export(storage, None, 10).unwrap();
+
+        let used = &mut MockStorage::default();
+        limiter.init(used, &Rate::PerBlock(2)).unwrap();
+        limiter.limit(used, &env, "b").unwrap();
+        limiter
+            .import(used, &env, Some(&page.config), &page.keys)
+            .unwrap_err();
+        assert_eq!(limiter.query_limit(used).unwrap(), Rate::PerBlock(2));
+        // Keys may still be imported under the existing config.
+        limiter.import(used, &env, None, &page.keys).unwrap();
+
+        // An initialized limiter without keys keeps its storage version.
+        let legacy = &mut MockStorage::default();
+        RateLimiter::new("rl", "r")
+            .init(legacy, &Rate::PerBlock(2))
+            .unwrap();
+        limiter
+            .import(legacy, &env, Some(&page.config), &[])
+            .unwrap();
+        assert_eq!(limiter.query_limit(legacy).unwrap(), Rate::Blocks(30));
+        assert_eq!(limiter.storage_version(legacy).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_import_snapshotted() {
+        let limiter =
+            RateLimiter::new_snapshotted("rl", "r", "c", "rlc", "rc").with_rate_updates("u");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(1)).unwrap();
+        env.block.height = 50;
+        limiter
+            .update_rate(storage, &env, Rate::PerBlock(2), RateTransition::Reset)
+            .unwrap();
+        env.block.height = 60;
+        let mut config = limiter.query_config(storage).unwrap();
+        config.rate_limit = Rate::PerBlock(3);
+        limiter.import(storage, &env, Some(&config), &[]).unwrap();
+
+        for (height, rate) in [(10, 1), (50, 1), (55, 2), (60, 2), (61, 3)] {
+            assert_eq!(
+                limiter.query_limit_at_height(storage, height).unwrap(),
+                Rate::PerBlock(rate)
+            );
+        }
+    }
+
+    #[test]
+    fn test_import_disabled_feature() {
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        LIMITER.init(storage, &Rate::PerBlock(2)).unwrap();
+        LIMITER
+            .set_new_key_limit(
+                storage,
+                Some(&NewKeyLimit {
+                    max_new_keys: 10,
+                    window_blocks: 5,
+                }),
+            )
+            .unwrap();
+        let page = LIMITER.export(storage, None, 10).unwrap();
+        RateLimiter::new("rl", "r")
+            .import(&mut MockStorage::default(), &env, Some(&page.config), &[])
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,464 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Empty, Order, StdError, StdResult, Storage};
+use cw_storage_plus::{Bound, Item, Map};
+
+use crate::{RateLimitError, RateLimiter};
+
+/// A cap on units limited across all keys per window, part of which
+/// is reserved for priority keys.
+#[cw_serde]
+pub struct GlobalLimit {
+    /// Units all keys together may use per window.
+    pub limit: u64,
+    /// Units of `limit` that only priority keys may use. Must not be
+    /// more than `limit`.
+    pub reserved: u64,
+    /// Length of a window in blocks. Windows are aligned to
+    /// multiples of this value. Must be non-zero.
+    pub window_blocks: u64,
+}
+
+#[cw_serde]
+pub struct PoolUsage {
+    pub used: u64,
+    pub remaining: u64,
+}
+
+#[cw_serde]
+pub struct GlobalUsage {
+    /// The global limit, if one is set.
+    pub global_limit: Option<GlobalLimit>,
+    /// Units available to every key.
+    pub shared: PoolUsage,
+    /// Units available only to priority keys.
+    pub reserved: PoolUsage,
+    /// First height of the next window.
+    pub window_end: u64,
+}
+
+#[cw_serde]
+#[derive(Default)]
+struct GlobalWindow {
+    window_start: u64,
+    shared: u64,
+    reserved: u64,
+}
+
+/// The global window as it will be once the units checked against it
+/// so far are committed.
+pub(crate) struct PendingGlobal {
+    limit: GlobalLimit,
+    window: GlobalWindow,
+}
+
+pub(crate) struct Global<'a, 'b> {
+    limit: Item<'a, GlobalLimit>,
+    window: Item<'a, GlobalWindow>,
+    priority: Map<'a, &'b str, Empty>,
+}
+
+impl GlobalLimit {
+    fn window_start(&self, height: u64) -> u64 {
+        height - height % self.window_blocks
+    }
+
+    fn usage(&self, window: &GlobalWindow) -> (PoolUsage, PoolUsage) {
+        let shared = self.limit - self.reserved;
+        (
+            PoolUsage {
+                used: window.shared,
+                remaining: shared.saturating_sub(window.shared),
+            },
+            PoolUsage {
+                used: window.reserved,
+                remaining: self.reserved.saturating_sub(window.reserved),
+            },
+        )
+    }
+}
+
+impl<'a, 'b> Global<'a, 'b> {
+    pub(crate) const fn new(
+        limit_key: &'a str,
+        window_key: &'a str,
+        priority_key: &'a str,
+    ) -> Self {
+        Self {
+            limit: Item::new(limit_key),
+            window: Item::new(window_key),
+            priority: Map::new(priority_key),
+        }
+    }
+
+    pub(crate) fn config(&self, storage: &dyn Storage) -> StdResult<Option<GlobalLimit>> {
+        self.limit.may_load(storage)
+    }
+
+    fn window(
+        &self,
+        storage: &dyn Storage,
+        limit: &GlobalLimit,
+        height: u64,
+    ) -> StdResult<GlobalWindow> {
+        let window_start = limit.window_start(height);
+        Ok(self
+            .window
+            .may_load(storage)?
+            .filter(|w| w.window_start == window_start)
+            .unwrap_or(GlobalWindow {
+                window_start,
+                ..Default::default()
+            }))
+    }
+
+    /// The window containing HEIGHT, or `None` if no limit is set.
+    pub(crate) fn pending(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+    ) -> StdResult<Option<PendingGlobal>> {
+        Ok(match self.limit.may_load(storage)? {
+            Some(limit) => Some(PendingGlobal {
+                window: self.window(storage, &limit, height)?,
+                limit,
+            }),
+            None => None,
+        })
+    }
+
+    /// Checks that COST units for KEY at HEIGHT fit in PENDING, and
+    /// takes them from it. Priority keys use the reserved pool before
+    /// the shared one. Nothing is saved until `commit`.
+    pub(crate) fn check(
+        &self,
+        storage: &dyn Storage,
+        pending: &mut PendingGlobal,
+        height: u64,
+        key: &str,
+        cost: u64,
+    ) -> Result<(), RateLimitError> {
+        let PendingGlobal { limit, window } = pending;
+        let (shared, reserved) = limit.usage(window);
+        let from_reserved = if self.priority.has(storage, key) {
+            reserved.remaining.min(cost)
+        } else {
+            0
+        };
+        let from_shared = cost - from_reserved;
+        if from_shared > shared.remaining {
+            return Err(RateLimitError::GlobalLimited {
+                key: key.to_string(),
+                blocks_remaining: window.window_start + limit.window_blocks - height,
+            });
+        }
+        window.reserved += from_reserved;
+        window.shared += from_shared;
+        Ok(())
+    }
+
+    /// Saves units taken from PENDING by `check`.
+    pub(crate) fn commit(
+        &self,
+        storage: &mut dyn Storage,
+        pending: &PendingGlobal,
+    ) -> StdResult<()> {
+        self.window.save(storage, &pending.window)
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables a global limit, stored under LIMIT_KEY, WINDOW_KEY and
+    /// PRIORITY_KEY. Keys are only limited globally once a limit has
+    /// been set with `set_global_limit`.
+    pub const fn with_global_limit(
+        mut self,
+        limit_key: &'a str,
+        window_key: &'a str,
+        priority_key: &'a str,
+    ) -> Self {
+        self.global = Some(Global::new(limit_key, window_key, priority_key));
+        self
+    }
+
+    /// Sets the global limit, or removes it if GLOBAL_LIMIT is
+    /// `None`. Units already used this window count towards the new
+    /// limit. Errors if the global limit is not enabled.
+    pub fn set_global_limit(
+        &self,
+        storage: &mut dyn Storage,
+        global_limit: Option<&GlobalLimit>,
+    ) -> StdResult<()> {
+        let global = self.global()?;
+        match global_limit {
+            Some(limit) if limit.window_blocks == 0 => Err(StdError::generic_err(
+                "global limit window must be non-zero",
+            )),
+            Some(limit) if limit.reserved > limit.limit => Err(StdError::generic_err(
+                "reserved units must not be more than the global limit",
+            )),
+            Some(limit) => global.limit.save(storage, limit),
+            None => {
+                global.limit.remove(storage);
+                global.window.remove(storage);
+                Ok(())
+            }
+        }
+    }
+
+    /// Allows KEY to use reserved capacity if PRIORITY, and otherwise
+    /// stops it. Callers are responsible for checking that the sender
+    /// is authorized to do this. Errors if the global limit is not
+    /// enabled.
+    pub fn set_priority(
+        &self,
+        storage: &mut dyn Storage,
+        key: &str,
+        priority: bool,
+    ) -> StdResult<()> {
+        let global = self.global()?;
+        if priority {
+            global.priority.save(storage, key, &Empty {})
+        } else {
+            global.priority.remove(storage, key);
+            Ok(())
+        }
+    }
+
+    /// Gets the use of the shared and reserved pools in the window
+    /// containing HEIGHT. Errors if the global limit is not enabled.
+    pub fn query_global_usage(&self, storage: &dyn Storage, height: u64) -> StdResult<GlobalUsage> {
+        let global = self.global()?;
+        let limit = match global.limit.may_load(storage)? {
+            Some(limit) => limit,
+            None => {
+                let empty = PoolUsage {
+                    used: 0,
+                    remaining: 0,
+                };
+                return Ok(GlobalUsage {
+                    global_limit: None,
+                    shared: empty.clone(),
+                    reserved: empty,
+                    window_end: height,
+                });
+            }
+        };
+        let window = global.window(storage, &limit, height)?;
+        let (shared, reserved) = limit.usage(&window);
+        Ok(GlobalUsage {
+            window_end: window.window_start + limit.window_blocks,
+            global_limit: Some(limit),
+            shared,
+            reserved,
+        })
+    }
+
+    /// Lists keys that may use reserved capacity, in ascending order.
+    /// Errors if the global limit is not enabled.
+    pub fn query_priority_keys(
+        &self,
+        storage: &dyn Storage,
+        start_after: Option<String>,
+        limit: usize,
+    ) -> StdResult<Vec<String>> {
+        self.global()?
+            .priority
+            .keys(
+                storage,
+                start_after.map(|s| Bound::ExclusiveRaw(s.into_bytes())),
+                None,
+                Order::Ascending,
+            )
+            .take(limit)
+            .collect()
+    }
+
+    // The global window at HEIGHT, if a global limit is set.
+    pub(crate) fn pending_global(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+    ) -> StdResult<Option<PendingGlobal>> {
+        match &self.global {
+            Some(global) => global.pending(storage, height),
+            None => Ok(None),
+        }
+    }
+
+    // Checks COST units for KEY against PENDING, the global window
+    // from `pending_global`, taking them from it if they fit.
+    pub(crate) fn check_global(
+        &self,
+        storage: &dyn Storage,
+        pending: Option<&mut PendingGlobal>,
+        height: u64,
+        key: &str,
+        cost: u64,
+    ) -> Result<(), RateLimitError> {
+        match (&self.global, pending) {
+            (Some(global), Some(pending)) => global.check(storage, pending, height, key, cost),
+            _ => Ok(()),
+        }
+    }
+
+    // Saves units taken from PENDING by `check_global`.
+    pub(crate) fn commit_global(
+        &self,
+        storage: &mut dyn Storage,
+        pending: Option<&PendingGlobal>,
+    ) -> StdResult<()> {
+        match (&self.global, pending) {
+            (Some(global), Some(pending)) => global.commit(storage, pending),
+            _ => Ok(()),
+        }
+    }
+
+    fn global(&self) -> StdResult<&Global<'a, 'b>> {
+        self.global
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not have a global limit"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::Rate;
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r").with_global_limit("gl", "glw", "glp");
+
+    #[test]
+    fn test_reserved_capacity() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::PerBlock(5)).unwrap();
+        LIMITER
+            .set_global_limit(
+                storage,
+                Some(&GlobalLimit {
+                    limit: 4,
+                    reserved: 2,
+                    window_blocks: 10,
+                }),
+            )
+            .unwrap();
+        LIMITER.set_priority(storage, "treasury", true).unwrap();
+
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit_many(storage, &env, &[("b", 1)]).unwrap();
+        // Public traffic has used the shared pool.
+        env.block.height = 105;
+        assert_eq!(
+            LIMITER.limit(storage, &env, "a").unwrap_err(),
+            RateLimitError::GlobalLimited {
+                key: "a".to_string(),
+                blocks_remaining: 5
+            }
+        );
+        LIMITER
+            .limit_many(storage, &env, &[("treasury", 2)])
+            .unwrap();
+        LIMITER.limit(storage, &env, "treasury").unwrap_err();
+        assert_eq!(
+            LIMITER.query_global_usage(storage, 105).unwrap(),
+            GlobalUsage {
+                global_limit: Some(GlobalLimit {
+                    limit: 4,
+                    reserved: 2,
+                    window_blocks: 10,
+                }),
+                shared: PoolUsage {
+                    used: 2,
+                    remaining: 0,
+                },
+                reserved: PoolUsage {
+                    used: 2,
+                    remaining: 0,
+                },
+                window_end: 110,
+            }
+        );
+        assert_eq!(
+            LIMITER.query_priority_keys(storage, None, 10).unwrap(),
+            vec!["treasury".to_string()]
+        );
+
+        // Priority keys use the shared pool once the reserved one is
+        // used up.
+        env.block.height = 110;
+        LIMITER
+            .limit_many(storage, &env, &[("treasury", 3)])
+            .unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "b").unwrap_err();
+
+        LIMITER.set_priority(storage, "treasury", false).unwrap();
+        LIMITER.set_global_limit(storage, None).unwrap();
+        LIMITER.limit(storage, &env, "b").unwrap();
+    }
+
+    #[test]
+    fn test_failed_limit_takes_nothing() {
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        LIMITER.init(storage, &Rate::PerBlock(2)).unwrap();
+        LIMITER
+            .set_global_limit(
+                storage,
+                Some(&GlobalLimit {
+                    limit: 4,
+                    reserved: 0,
+                    window_blocks: 10,
+                }),
+            )
+            .unwrap();
+        let used = |storage: &MockStorage| {
+            LIMITER
+                .query_global_usage(storage, env.block.height)
+                .unwrap()
+                .shared
+                .used
+        };
+
+        // Over the global limit.
+        LIMITER
+            .limit_many(storage, &env, &[("a", 1), ("b", 5)])
+            .unwrap_err();
+        assert_eq!(used(storage), 0);
+        // Over a key's own limit, after an earlier key fit globally.
+        LIMITER
+            .limit_many(storage, &env, &[("a", 1), ("b", 3)])
+            .unwrap_err();
+        assert_eq!(used(storage), 0);
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        assert_eq!(used(storage), 2);
+    }
+
+    #[test]
+    fn test_invalid_global_limit() {
+        let storage = &mut MockStorage::default();
+        for limit in [
+            GlobalLimit {
+                limit: 1,
+                reserved: 2,
+                window_blocks: 10,
+            },
+            GlobalLimit {
+                limit: 1,
+                reserved: 0,
+                window_blocks: 0,
+            },
+        ] {
+            LIMITER.set_global_limit(storage, Some(&limit)).unwrap_err();
+        }
+        RateLimiter::new("rl", "r")
+            .set_priority(storage, "a", true)
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,317 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{StdError, StdResult, Storage};
+use cw_storage_plus::{Item, Map};
+
+use crate::RateLimiter;
+
+/// Extra units given to keys the limiter has never seen, so that new
+/// keys may burst while steady-state rates stay strict. A key uses
+/// its grace only when it would otherwise be limited.
+#[cw_serde]
+pub struct GraceAllowance {
+    /// Units each new key may use beyond its limit.
+    pub units: u64,
+    /// Blocks after a key is first seen during which it may use its
+    /// grace, or `None` if grace does not expire. Must be non-zero.
+    pub blocks: Option<u64>,
+}
+
+#[cw_serde]
+pub(crate) struct Grace {
+    remaining: u64,
+    expires_at_height: Option<u64>,
+}
+
+impl Grace {
+    fn available(&self, height: u64) -> u64 {
+        match self.expires_at_height {
+            Some(expires) if expires <= height => 0,
+            _ => self.remaining,
+        }
+    }
+
+    /// Uses COST units at HEIGHT, returning false, and leaving the
+    /// grace as-is, if there are not enough.
+    pub(crate) fn spend(&mut self, height: u64, cost: u64) -> bool {
+        if self.available(height) < cost {
+            return false;
+        }
+        self.remaining -= cost;
+        true
+    }
+}
+
+pub(crate) struct GraceStore<'a, 'b> {
+    allowance: Item<'a, GraceAllowance>,
+    grace: Map<'a, &'b str, Grace>,
+}
+
+impl<'a, 'b> GraceStore<'a, 'b> {
+    pub(crate) const fn new(allowance_key: &'a str, grace_key: &'a str) -> Self {
+        Self {
+            allowance: Item::new(allowance_key),
+            grace: Map::new(grace_key),
+        }
+    }
+
+    /// KEY's grace at HEIGHT. Keys that have never been SEEN are
+    /// given grace under the current allowance, which is only stored
+    /// once saved with `save`. Keys are only given grace once.
+    pub(crate) fn load(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+        key: &str,
+        seen: bool,
+    ) -> StdResult<Option<Grace>> {
+        Ok(match self.grace.may_load(storage, key)? {
+            Some(grace) => Some(grace),
+            None if !seen => self.allowance.may_load(storage)?.map(|allowance| Grace {
+                remaining: allowance.units,
+                expires_at_height: allowance.blocks.map(|blocks| height + blocks),
+            }),
+            None => None,
+        })
+    }
+
+    pub(crate) fn save(
+        &self,
+        storage: &mut dyn Storage,
+        key: &str,
+        grace: &Grace,
+    ) -> StdResult<()> {
+        self.grace.save(storage, key, grace)
+    }
+
+    /// Grace units KEY may use at HEIGHT. For keys that have never
+    /// been seen, this is what they would be given.
+    pub(crate) fn remaining(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+        key: &str,
+        seen: bool,
+    ) -> StdResult<u64> {
+        Ok(self
+            .load(storage, height, key, seen)?
+            .map_or(0, |grace| grace.available(height)))
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables a grace allowance for new keys, stored under
+    /// ALLOWANCE_KEY and GRACE_KEY. Keys are only given grace while
+    /// an allowance is set with `set_grace_allowance`.
+    pub const fn with_grace(mut self, allowance_key: &'a str, grace_key: &'a str) -> Self {
+        self.grace = Some(GraceStore::new(allowance_key, grace_key));
+        self
+    }
+
+    /// Sets the grace allowance for keys seen from now on, or stops
+    /// giving grace if ALLOWANCE is `None`. Keys keep grace they have
+    /// already been given. Errors if grace is not enabled.
+    pub fn set_grace_allowance(
+        &self,
+        storage: &mut dyn Storage,
+        allowance: Option<&GraceAllowance>,
+    ) -> StdResult<()> {
+        let grace = self.grace_store()?;
+        match allowance {
+            Some(GraceAllowance {
+                blocks: Some(0), ..
+            }) => Err(StdError::generic_err("grace period must be non-zero")),
+            Some(allowance) => grace.allowance.save(storage, allowance),
+            None => {
+                grace.allowance.remove(storage);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn query_grace_allowance(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<GraceAllowance>> {
+        self.grace_store()?.allowance.may_load(storage)
+    }
+
+    fn grace_store(&self) -> StdResult<&GraceStore<'a, 'b>> {
+        self.grace
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not have a grace allowance"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::{NewKeyLimit, Rate, RateLimitError};
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r").with_grace("ga", "g");
+
+    #[test]
+    fn test_grace() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::Blocks(10)).unwrap();
+        // Seen before grace was set.
+        LIMITER.limit(storage, &env, "old").unwrap();
+        LIMITER
+            .set_grace_allowance(
+                storage,
+                Some(&GraceAllowance {
+                    units: 2,
+                    blocks: Some(5),
+                }),
+            )
+            .unwrap();
+        LIMITER.limit(storage, &env, "old").unwrap_err();
+
+        assert_eq!(
+            LIMITER
+                .query_key_state(storage, &env, "new".to_string())
+                .unwrap()
+                .grace,
+            2
+        );
+        LIMITER.limit(storage, &env, "new").unwrap();
+        LIMITER.limit_many(storage, &env, &[("new", 2)]).unwrap();
+        assert!(matches!(
+            LIMITER.limit(storage, &env, "new").unwrap_err(),
+            RateLimitError::Limited { .. }
+        ));
+        assert_eq!(
+            LIMITER
+                .query_key_state(storage, &env, "new".to_string())
+                .unwrap()
+                .grace,
+            0
+        );
+
+        // Grace expires.
+        LIMITER.limit(storage, &env, "newer").unwrap();
+        LIMITER.limit(storage, &env, "newer").unwrap();
+        env.block.height = 105;
+        assert_eq!(
+            LIMITER
+                .query_key_state(storage, &env, "newer".to_string())
+                .unwrap()
+                .grace,
+            0
+        );
+        LIMITER.limit(storage, &env, "newer").unwrap_err();
+
+        // Keys are only given grace once, even if reset.
+        LIMITER.reset(storage, &env, "new").unwrap();
+        LIMITER.limit(storage, &env, "new").unwrap();
+        LIMITER.limit(storage, &env, "new").unwrap_err();
+    }
+
+    #[test]
+    fn test_grace_counts_as_new_key() {
+        let limiter = RateLimiter::new("rl", "r")
+            .with_grace("ga", "g")
+            .with_new_key_limit("nkl", "nkw");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(0)).unwrap();
+        limiter
+            .set_grace_allowance(
+                storage,
+                Some(&GraceAllowance {
+                    units: 2,
+                    blocks: None,
+                }),
+            )
+            .unwrap();
+        limiter
+            .set_new_key_limit(
+                storage,
+                Some(&NewKeyLimit {
+                    max_new_keys: 1,
+                    window_blocks: 10,
+                }),
+            )
+            .unwrap();
+
+        // Served only from grace, but still admitted as new, and only
+        // once.
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "a").unwrap();
+        assert!(matches!(
+            limiter.limit(storage, &env, "b").unwrap_err(),
+            RateLimitError::NewKeyLimited { .. }
+        ));
+        assert!(matches!(
+            limiter.limit_many(storage, &env, &[("c", 1)]).unwrap_err(),
+            RateLimitError::NewKeyLimited { .. }
+        ));
+        assert!(matches!(
+            limiter.limit(storage, &env, "a").unwrap_err(),
+            RateLimitError::Limited { .. }
+        ));
+    }
+
+    #[test]
+    fn test_failed_limit_keeps_grace() {
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        LIMITER.init(storage, &Rate::PerBlock(0)).unwrap();
+        LIMITER.reset(storage, &env, "old").unwrap();
+        LIMITER
+            .set_grace_allowance(
+                storage,
+                Some(&GraceAllowance {
+                    units: 2,
+                    blocks: None,
+                }),
+            )
+            .unwrap();
+
+        LIMITER
+            .limit_many(storage, &env, &[("a", 2), ("old", 1)])
+            .unwrap_err();
+        assert_eq!(LIMITER.query_rate_info(storage, "a").unwrap(), None);
+        assert_eq!(
+            LIMITER
+                .query_key_state(storage, &env, "a".to_string())
+                .unwrap()
+                .grace,
+            2
+        );
+        LIMITER.limit_many(storage, &env, &[("a", 2)]).unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+    }
+
+    #[test]
+    fn test_grace_when_blocked() {
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        LIMITER.init(storage, &Rate::PerBlock(0)).unwrap();
+        LIMITER
+            .set_grace_allowance(
+                storage,
+                Some(&GraceAllowance {
+                    units: 1,
+                    blocks: None,
+                }),
+            )
+            .unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+
+        LIMITER
+            .set_grace_allowance(
+                storage,
+                Some(&GraceAllowance {
+                    units: 1,
+                    blocks: Some(0),
+                }),
+            )
+            .unwrap_err();
+    }
+}
@@ -4,9 +4,45 @@ use cosmwasm_schema::cw_serde;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Env, StdError, StdResult, Storage};
-use cw_storage_plus::{Item, Map};
+use boosts::Boosted;
+use cosmwasm_std::{BlockInfo, Env, Event, StdError, StdResult, Storage};
+use cw_storage_plus::{Item, Map, SnapshotItem, SnapshotMap, Strategy};
 use thiserror::Error;
+use update::Effective;
+
+mod adaptive;
+mod boosts;
+mod compact;
+mod events;
+mod export;
+mod global;
+mod grace;
+mod migrate;
+#[cfg(test)]
+mod model;
+mod new_keys;
+mod query;
+mod stats;
+mod throttled;
+mod tripwire;
+mod update;
+mod vouchers;
+
+pub use adaptive::{AdaptiveRate, AdaptiveRateState};
+pub use boosts::{Boost, BoostedKey};
+pub use compact::CompactRateLimiter;
+pub use events::RateUsage;
+pub use export::{ExportedKey, StateExport};
+pub use global::{GlobalLimit, GlobalUsage, PoolUsage};
+pub use grace::GraceAllowance;
+pub use migrate::{MigrationStatus, STORAGE_VERSION};
+pub use new_keys::NewKeyLimit;
+pub use query::{KeyState, RateLimiterConfig, RateLimiterQueryMsg};
+pub use stats::{KeyStats, TotalStats};
+pub use throttled::ThrottledKey;
+pub use tripwire::{TripCause, Tripped, Tripwire, TripwireState};
+pub use update::RateTransition;
+pub use vouchers::{voucher_sign_bytes, Bypass, SignedVoucher, Voucher, VoucherAuthority};
 
 // Need to derive ourselves instead of cw_serde as we have a custom
 // partial equal implementation.
@@ -19,14 +55,67 @@ pub enum Rate {
 
 #[cw_serde]
 #[derive(Default)]
-struct RateInfo {
-    last_updated_height: u64,
-    this_block: u64,
+pub struct RateInfo {
+    pub last_updated_height: u64,
+    pub this_block: u64,
+    /// Under net-flow limiting and `Rate::PerBlock`, inbound units
+    /// credited in `last_updated_height` that have not yet been
+    /// offset by outbound ones.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub credit: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
 }
 
 pub struct RateLimiter<'a, 'b> {
     rate_limit: Item<'a, Rate>,
     rates: Map<'a, &'b str, RateInfo>,
+    snapshots: Option<Snapshots<'a, 'b>>,
+    stats: Option<stats::Stats<'a, 'b>>,
+    new_keys: Option<new_keys::NewKeys<'a>>,
+    rate_changes: Option<update::RateChanges<'a>>,
+    throttled: Option<throttled::ThrottledIndex<'a, 'b>>,
+    net_flow: bool,
+    tripwire: Option<tripwire::TripwireStore<'a>>,
+    version: Option<migrate::Version<'a, 'b>>,
+    adaptive: Option<adaptive::Adaptive<'a>>,
+    boosts: Option<boosts::Boosts<'a, 'b>>,
+    vouchers: Option<vouchers::Vouchers<'a, 'b>>,
+    global: Option<global::Global<'a, 'b>>,
+    grace: Option<grace::GraceStore<'a, 'b>>,
+}
+
+// Snapshotted views over the same primary namespaces as
+// `rate_limit` and `rates`. Writes through these also record the
+// previous value in a changelog keyed by block height.
+struct Snapshots<'a, 'b> {
+    rate_limit: SnapshotItem<'a, Rate>,
+    rates: SnapshotMap<'a, &'b str, RateInfo>,
+}
+
+// The result of limiting a key: the rate it was limited under, its
+// new state, and an event if the adaptive rate changed.
+struct Limited {
+    rate: Rate,
+    info: RateInfo,
+    adapted: Option<Event>,
+}
+
+// The writes limiting a set of keys will make, worked out in memory
+// so that nothing is written unless every key may be limited.
+#[derive(Default)]
+struct Plan<'k> {
+    // Keys charged against their quota, with the rate they were
+    // charged under and their new state.
+    charged: Vec<(&'k str, Rate, RateInfo)>,
+    // Keys the limiter has never seen, in the order they were first
+    // limited.
+    new_keys: Vec<&'k str>,
+    grace: Vec<(&'k str, grace::Grace)>,
+    bypasses: Vec<(&'k str, Vec<Bypass>)>,
+    global: Option<global::PendingGlobal>,
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -36,6 +125,24 @@ pub enum RateLimitError {
 
     #[error("rate limit reached for key ({key}). blocks until next chance: ({blocks_remaining})")]
     Limited { key: String, blocks_remaining: u64 },
+
+    #[error("too many new keys this window, can not admit ({key}). blocks until next chance: ({blocks_remaining})")]
+    NewKeyLimited { key: String, blocks_remaining: u64 },
+
+    #[error("global rate limit reached, can not limit ({key}). blocks until next chance: ({blocks_remaining})")]
+    GlobalLimited { key: String, blocks_remaining: u64 },
+
+    #[error("rate limiter tripped at height ({height}) and must be reset")]
+    Tripped { height: u64 },
+
+    #[error("voucher signature is invalid")]
+    InvalidVoucherSignature {},
+
+    #[error("voucher ({nonce}) has expired")]
+    VoucherExpired { nonce: u64 },
+
+    #[error("voucher ({nonce}) has already been used")]
+    VoucherUsed { nonce: u64 },
 }
 
 impl<'a> RateLimiter<'a, '_> {
@@ -43,11 +150,85 @@ impl<'a> RateLimiter<'a, '_> {
         Self {
             rate_limit: Item::new(rate_limit_key),
             rates: Map::new(rates_key),
+            snapshots: None,
+            stats: None,
+            new_keys: None,
+            rate_changes: None,
+            throttled: None,
+            net_flow: false,
+            tripwire: None,
+            version: None,
+            adaptive: None,
+            boosts: None,
+            vouchers: None,
+            global: None,
+            grace: None,
+        }
+    }
+
+    /// Creates a rate limiter that additionally records the value of
+    /// its config and of every key's `RateInfo` at each height they
+    /// change, so that they may be queried with
+    /// `query_limit_at_height` and `query_rate_info_at_height`.
+    ///
+    /// The primary namespaces are shared with `new`, so a limiter
+    /// created with `new` may later be switched to this
+    /// constructor. History is only available from that point on.
+    pub const fn new_snapshotted(
+        rate_limit_key: &'a str,
+        rates_key: &'a str,
+        checkpoints_key: &'a str,
+        rate_limit_changelog_key: &'a str,
+        rates_changelog_key: &'a str,
+    ) -> Self {
+        Self {
+            rate_limit: Item::new(rate_limit_key),
+            rates: Map::new(rates_key),
+            snapshots: Some(Snapshots {
+                rate_limit: SnapshotItem::new(
+                    rate_limit_key,
+                    checkpoints_key,
+                    rate_limit_changelog_key,
+                    Strategy::EveryBlock,
+                ),
+                rates: SnapshotMap::new(
+                    rates_key,
+                    checkpoints_key,
+                    rates_changelog_key,
+                    Strategy::EveryBlock,
+                ),
+            }),
+            stats: None,
+            new_keys: None,
+            rate_changes: None,
+            throttled: None,
+            net_flow: false,
+            tripwire: None,
+            version: None,
+            adaptive: None,
+            boosts: None,
+            vouchers: None,
+            global: None,
+            grace: None,
         }
     }
 
+    /// Enables net-flow limiting, where `credit` records inbound
+    /// units that offset outbound ones charged by `limit`.
+    pub const fn with_net_flow(mut self) -> Self {
+        self.net_flow = true;
+        self
+    }
+
+    /// Sets the rate limit. This should only be called once, before
+    /// any keys are limited. To change the rate afterwards, see
+    /// `update_rate`. Limiters created with `new_snapshotted` record
+    /// the rate as set at height zero.
     pub fn init(&self, storage: &mut dyn Storage, rate_limit: &Rate) -> StdResult<()> {
-        self.rate_limit.save(storage, rate_limit)
+        if let Some(version) = &self.version {
+            version.init(storage)?;
+        }
+        self.save_rate_limit(storage, rate_limit, 0)
     }
 
     pub fn limit(
@@ -56,54 +237,593 @@ impl<'a> RateLimiter<'a, '_> {
         env: &Env,
         key: &str,
     ) -> Result<(), RateLimitError> {
-        let RateInfo {
-            last_updated_height,
-            this_block,
-        } = self.rates.may_load(storage, key)?.unwrap_or_default();
-        let next_value = match self.rate_limit.load(storage)? {
-            Rate::PerBlock(limit) => {
-                let this_block = if last_updated_height == env.block.height {
-                    this_block + 1
-                } else {
-                    1
-                };
-
-                if this_block > limit {
-                    return Err(RateLimitError::Limited {
-                        blocks_remaining: 1,
-                        key: key.to_string(),
-                    });
+        self.limit_key(storage, env, key, false).map(|_| ())
+    }
+
+    /// Same as `limit`, but on success also returns events for
+    /// contracts to attach to their response. These are a
+    /// `rate_limit` event describing KEY's use of its quota (see
+    /// `RateUsage::event`), preceded by a `rate_limit_adapted` event
+    /// if the adaptive rate has changed since the last call to this
+    /// reported it. Changes made by `limit` and `limit_many` are
+    /// reported here, as they do not return events.
+    pub fn limit_with_event(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        key: &str,
+    ) -> Result<Vec<Event>, RateLimitError> {
+        let Limited {
+            rate,
+            info,
+            adapted,
+        } = self.limit_key(storage, env, key, true)?;
+        let usage = RateUsage::new(rate, &info, env.block.height).event(key);
+        Ok(adapted.into_iter().chain([usage]).collect())
+    }
+
+    /// Limits every (key, cost) pair in KEYS at once. Either every
+    /// key has enough quota and all are charged, or none are and the
+    /// error for the first key that did not is returned. A key may
+    /// appear more than once, in which case its costs add up.
+    ///
+    /// A cost of N is charged as N separate calls to `limit` in the
+    /// same block would be, except that under `Rate::Blocks(n)` a key
+    /// with an open window is charged all N units at once and must
+    /// then wait N * n blocks.
+    pub fn limit_many(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        keys: &[(&str, u64)],
+    ) -> Result<(), RateLimitError> {
+        let res = self.update_keys(storage, env, keys, false);
+        self.record_stats(storage, env.block.height, keys, res.is_ok())?;
+        res.map(|_| ())
+    }
+
+    fn limit_key(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        key: &str,
+        report: bool,
+    ) -> Result<Limited, RateLimitError> {
+        let res = self.update_keys(storage, env, &[(key, 1)], report);
+        self.record_stats(storage, env.block.height, &[(key, 1)], res.is_ok())?;
+        let (rate, plan, adapted) = res?;
+        let (rate, info) = match plan.charged.into_iter().next() {
+            Some((_, rate, info)) => (rate, info),
+            // Served from grace or vouchers.
+            None => (rate, self.rates.load(storage, key)?),
+        };
+        Ok(Limited {
+            rate,
+            info,
+            adapted,
+        })
+    }
+
+    // Counts a call limiting KEYS once for each distinct key in it.
+    fn record_stats(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        keys: &[(&str, u64)],
+        accepted: bool,
+    ) -> StdResult<()> {
+        if let Some(stats) = &self.stats {
+            for (i, (key, _)) in keys.iter().enumerate() {
+                if !keys[..i].iter().any(|(k, _)| k == key) {
+                    stats.record(storage, height, key, accepted)?;
                 }
-                this_block
-            }
-            Rate::Blocks(min_blocks) => {
-                let elapsed = env.block.height.saturating_sub(last_updated_height);
-                if elapsed < min_blocks {
-                    return Err(RateLimitError::Limited {
-                        blocks_remaining: min_blocks - elapsed,
-                        key: key.to_string(),
-                    });
+            }
+        }
+        Ok(())
+    }
+
+    // Charges KEYS, writing nothing unless every key may be
+    // charged. Returns the rate they were charged under, the writes
+    // made, and, if REPORT is set, an event if the adaptive rate
+    // changed.
+    fn update_keys<'k>(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        keys: &[(&'k str, u64)],
+        report: bool,
+    ) -> Result<(Rate, Plan<'k>, Option<Event>), RateLimitError> {
+        let height = env.block.height;
+        if let Some(tripwire) = &self.tripwire {
+            tripwire.check(storage)?;
+        }
+        let rate = self.current_rate(storage, height)?;
+        let mut plan = Plan {
+            global: self.pending_global(storage, height)?,
+            ..Default::default()
+        };
+        for (key, cost) in keys {
+            self.plan(storage, &env.block, rate, &mut plan, key, *cost)?;
+        }
+        self.commit(storage, &env.block, rate, &plan)?;
+        let adapted = self.adapt(storage, height, report)?;
+        if let Some(tripwire) = &self.tripwire {
+            for (key, cost) in keys {
+                tripwire.record(storage, height, key, *cost)?;
+            }
+        }
+        if let Some(adaptive) = &self.adaptive {
+            let units = keys.iter().fold(0u64, |n, (_, c)| n.saturating_add(*c));
+            adaptive.record(storage, units)?;
+        }
+        Ok((rate, plan, adapted))
+    }
+
+    // Checks that KEY, with stored state INFO, may use COST units
+    // under RATE in BLOCK, returning the rate it was charged under
+    // and its new state without saving it.
+    fn check(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        rate: Rate,
+        key: &str,
+        info: Option<RateInfo>,
+        cost: u64,
+    ) -> Result<(Rate, RateInfo), RateLimitError> {
+        let height = block.height;
+        let limited = |blocks_remaining| RateLimitError::Limited {
+            blocks_remaining,
+            key: key.to_string(),
+        };
+        match self.effective(storage, block, key, rate, info)? {
+            Effective::Active(rate, info) => rate
+                .charge(&info, height, cost)
+                .map(|info| (rate, info))
+                .map_err(limited),
+            Effective::BlockedUntil(until) => Err(limited(until - height)),
+        }
+    }
+
+    // Adds charging KEY COST units under RATE in BLOCK to PLAN, on
+    // top of what is already planned. If KEY does not have enough
+    // quota, uses its grace allowance or units granted to it by
+    // vouchers instead, leaving its state as-is.
+    fn plan<'k>(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        rate: Rate,
+        plan: &mut Plan<'k>,
+        key: &'k str,
+        cost: u64,
+    ) -> Result<(), RateLimitError> {
+        let height = block.height;
+        let charged = plan.charged.iter().position(|(k, _, _)| *k == key);
+        let info = match charged {
+            Some(i) => Some(plan.charged[i].2.clone()),
+            None => self.rates.may_load(storage, key)?,
+        };
+        if info.is_none() && !plan.new_keys.contains(&key) {
+            plan.new_keys.push(key);
+            // Grants the key its grace, whether or not it is used.
+            if let Some(grace) = &self.grace {
+                if let Some(grace) = grace.load(storage, height, key, false)? {
+                    plan.grace.push((key, grace));
                 }
-                0
             }
+        }
+        let checked = match self.check(storage, block, rate, key, info, cost) {
+            Ok(checked) => Some(checked),
+            Err(e @ RateLimitError::Limited { .. }) => {
+                if !(self.plan_grace(storage, height, plan, key, cost)?
+                    || self.plan_bypass(storage, height, plan, key, cost)?)
+                {
+                    return Err(e);
+                }
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        self.check_global(storage, plan.global.as_mut(), height, key, cost)?;
+        match (checked, charged) {
+            (Some(checked), Some(i)) => plan.charged[i] = (key, checked.0, checked.1),
+            (Some((rate, info)), None) => plan.charged.push((key, rate, info)),
+            (None, _) => (),
+        }
+        Ok(())
+    }
+
+    // Uses COST of KEY's grace in PLAN, returning false if it does
+    // not have enough.
+    fn plan_grace<'k>(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+        plan: &mut Plan<'k>,
+        key: &'k str,
+        cost: u64,
+    ) -> StdResult<bool> {
+        let store = match &self.grace {
+            Some(store) => store,
+            None => return Ok(false),
         };
-        self.rates.save(
-            storage,
-            key,
-            &RateInfo {
-                last_updated_height: env.block.height,
-                this_block: next_value,
+        let i = match plan.grace.iter().position(|(k, _)| *k == key) {
+            Some(i) => i,
+            None => match store.load(storage, height, key, true)? {
+                Some(grace) => {
+                    plan.grace.push((key, grace));
+                    plan.grace.len() - 1
+                }
+                None => return Ok(false),
             },
-        )?;
+        };
+        Ok(plan.grace[i].1.spend(height, cost))
+    }
+
+    // Uses COST of KEY's bypass units in PLAN, returning false if it
+    // does not have enough.
+    fn plan_bypass<'k>(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+        plan: &mut Plan<'k>,
+        key: &'k str,
+        cost: u64,
+    ) -> StdResult<bool> {
+        let store = match &self.vouchers {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+        let i = match plan.bypasses.iter().position(|(k, _)| *k == key) {
+            Some(i) => i,
+            None => {
+                plan.bypasses.push((key, store.load(storage, key)?));
+                plan.bypasses.len() - 1
+            }
+        };
+        Ok(vouchers::spend(&mut plan.bypasses[i].1, height, cost))
+    }
+
+    // Makes the writes in PLAN, made under RATE in BLOCK. Errors,
+    // writing nothing, if its new keys can not be admitted.
+    fn commit(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        rate: Rate,
+        plan: &Plan,
+    ) -> Result<(), RateLimitError> {
+        let height = block.height;
+        if let Some(limit) = &self.new_keys {
+            limit.admit(storage, height, &plan.new_keys)?;
+        }
+        self.commit_global(storage, plan.global.as_ref())?;
+        if let Some(store) = &self.grace {
+            for (key, grace) in &plan.grace {
+                store.save(storage, key, grace)?;
+            }
+        }
+        if let Some(store) = &self.vouchers {
+            for (key, bypasses) in &plan.bypasses {
+                store.save(storage, height, key, bypasses)?;
+            }
+        }
+        for (key, rate, info) in &plan.charged {
+            self.remove_expired_boost(storage, block, key)?;
+            self.save_rate_info(storage, *rate, key, info, height)?;
+        }
+        for key in &plan.new_keys {
+            if !plan.charged.iter().any(|(k, _, _)| k == key) {
+                // Served entirely from grace or vouchers, but now
+                // seen, so it is not counted as new again.
+                self.save_rate_info(storage, rate, key, &RateInfo::default(), height)?;
+            }
+        }
         Ok(())
     }
 
+    /// Credits UNITS of inbound flow to KEY so that limits apply to
+    /// its net outflow over the window. See `Rate::credit` for how
+    /// units are applied. Does nothing for keys that have never been
+    /// limited. Errors if net-flow limiting is not enabled.
+    pub fn credit(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        key: &str,
+        units: u64,
+    ) -> StdResult<()> {
+        if !self.net_flow {
+            return Err(StdError::generic_err(
+                "rate limiter does not limit net flow",
+            ));
+        }
+        let height = env.block.height;
+        let rate = self.current_rate(storage, height)?;
+        let info = match self.rates.may_load(storage, key)? {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        match self.effective(storage, &env.block, key, rate, Some(info))? {
+            Effective::Active(rate, info) => {
+                let info = rate.credit(&info, height, units);
+                self.remove_expired_boost(storage, &env.block, key)?;
+                self.save_rate_info(storage, rate, key, &info, height)
+            }
+            // Windows under a previous rate can not be shortened.
+            Effective::BlockedUntil(_) => Ok(()),
+        }
+    }
+
+    /// Clears KEY's usage so that it starts fresh with a full quota,
+    /// and returns a `rate_limit_reset` event recording this. KEY is
+    /// still considered to have been seen before, so it does not
+    /// count against the cap on new keys.
+    pub fn reset(&self, storage: &mut dyn Storage, env: &Env, key: &str) -> StdResult<Event> {
+        let height = env.block.height;
+        let rate = self.current_rate(storage, height)?;
+        self.save_rate_info(storage, rate, key, &RateInfo::default(), height)?;
+        Ok(Event::new("rate_limit_reset").add_attribute("key", key))
+    }
+
     pub fn query_limit(&self, storage: &dyn Storage) -> Result<Rate, StdError> {
         self.rate_limit.load(storage)
     }
+
+    /// Gets the rate in effect at HEIGHT, including any adaptive rate.
+    /// Unlike `query_limit`, a rate set by `update_rate` is only
+    /// returned once it has taken effect.
+    pub fn query_current_rate(&self, storage: &dyn Storage, height: u64) -> StdResult<Rate> {
+        self.current_rate(storage, height)
+    }
+
+    /// Gets the rate limiting state for KEY, if it has ever been
+    /// limited.
+    pub fn query_rate_info(&self, storage: &dyn Storage, key: &str) -> StdResult<Option<RateInfo>> {
+        self.rates.may_load(storage, key)
+    }
+
+    /// Gets the rate limit as it was at the start of block HEIGHT,
+    /// before any changes made during that block. Errors if the
+    /// limiter was not created with `new_snapshotted`.
+    pub fn query_limit_at_height(&self, storage: &dyn Storage, height: u64) -> StdResult<Rate> {
+        self.snapshots()?
+            .rate_limit
+            .may_load_at_height(storage, height)?
+            .ok_or_else(|| StdError::not_found("rate limit"))
+    }
+
+    /// Gets the rate limiting state for KEY as it was at the start of
+    /// block HEIGHT, before any changes made during that block. Errors
+    /// if the limiter was not created with `new_snapshotted`.
+    pub fn query_rate_info_at_height(
+        &self,
+        storage: &dyn Storage,
+        key: &str,
+        height: u64,
+    ) -> StdResult<Option<RateInfo>> {
+        self.snapshots()?
+            .rates
+            .may_load_at_height(storage, key, height)
+    }
+
+    // Gets the rate in effect at HEIGHT.
+    fn current_rate(&self, storage: &dyn Storage, height: u64) -> StdResult<Rate> {
+        if let Some(adaptive) = &self.adaptive {
+            if let Some(rate) = adaptive.switching_at(storage, height)? {
+                return Ok(rate);
+            }
+            if let Some(rate) = adaptive.rate_at(storage, height)? {
+                return Ok(rate);
+            }
+        }
+        let rate = self.rate_limit.load(storage)?;
+        match &self.rate_changes {
+            Some(changes) => changes.rate_at(storage, rate, height),
+            None => Ok(rate),
+        }
+    }
+
+    fn remove_expired_boost(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        key: &str,
+    ) -> StdResult<()> {
+        match &self.boosts {
+            Some(boosts) => boosts.remove_expired(storage, block, key),
+            None => Ok(()),
+        }
+    }
+
+    // Moves the adaptive rate, if one is set, to the window
+    // containing HEIGHT. If REPORT is set, returns an event if it has
+    // changed since it was last reported.
+    fn adapt(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        report: bool,
+    ) -> StdResult<Option<Event>> {
+        match &self.adaptive {
+            Some(adaptive) => adaptive.update(storage, height, report),
+            None => Ok(None),
+        }
+    }
+
+    // Translates INFO, KEY's stored state, to the rate in effect for
+    // it in BLOCK. This is its boost if it has one, and otherwise
+    // RATE.
+    fn effective(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        key: &str,
+        rate: Rate,
+        info: Option<RateInfo>,
+    ) -> StdResult<Effective> {
+        let height = block.height;
+        let info = match &self.boosts {
+            Some(boosts) => match boosts.apply(storage, block, key, rate, info)? {
+                Boosted::Active(effective) => return Ok(effective),
+                Boosted::Base(info) => info,
+            },
+            None => info,
+        };
+        if let (Some(adaptive), Some(info)) = (&self.adaptive, &info) {
+            if let Some(effective) = adaptive.translate(storage, rate, info, height)? {
+                return Ok(effective);
+            }
+        }
+        match &self.rate_changes {
+            Some(changes) => changes.effective(storage, rate, info, height),
+            None => Ok(Effective::Active(rate, info.unwrap_or_default())),
+        }
+    }
+
+    // Saves RATE as the rate limit at HEIGHT, recording the previous
+    // one if snapshots are enabled.
+    fn save_rate_limit(
+        &self,
+        storage: &mut dyn Storage,
+        rate: &Rate,
+        height: u64,
+    ) -> StdResult<()> {
+        match &self.snapshots {
+            Some(snapshots) => snapshots.rate_limit.save(storage, rate, height),
+            None => self.rate_limit.save(storage, rate),
+        }
+    }
+
+    fn snapshots(&self) -> StdResult<&Snapshots<'a, '_>> {
+        self.snapshots
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not record snapshots"))
+    }
+
+    // Saves INFO, KEY's state under RATE at HEIGHT.
+    fn save_rate_info(
+        &self,
+        storage: &mut dyn Storage,
+        rate: Rate,
+        key: &str,
+        info: &RateInfo,
+        height: u64,
+    ) -> StdResult<()> {
+        if let Some(throttled) = &self.throttled {
+            throttled.update(storage, rate, key, info, height)?;
+        }
+        if let Some(version) = &self.version {
+            version.record_write(storage, key)?;
+        }
+        match &self.snapshots {
+            Some(snapshots) => snapshots.rates.save(storage, key, info, height),
+            None => self.rates.save(storage, key, info),
+        }
+    }
+}
+
+impl RateInfo {
+    // Under `Rate::PerBlock`, the units used and credited in the
+    // block at HEIGHT.
+    fn this_window(&self, height: u64) -> (u64, u64) {
+        if self.last_updated_height == height {
+            (self.this_block, self.credit)
+        } else {
+            (0, 0)
+        }
+    }
+
+    // Under `Rate::Blocks(blocks)`, the height at which the key may
+    // next be limited. `this_block` holds the number of units charged
+    // in the window, with zero (the value before costs were
+    // supported) meaning one. The default state has never been
+    // charged, so its window is already over even near genesis.
+    pub(crate) fn blocks_window_end(&self, blocks: u64) -> u64 {
+        if *self == RateInfo::default() {
+            return 0;
+        }
+        self.last_updated_height
+            .saturating_add(blocks.saturating_mul(self.this_block.max(1)))
+    }
 }
 
 impl Rate {
+    /// Charges COST units to a key with state INFO at HEIGHT,
+    /// returning its new state. If there is not enough quota left,
+    /// returns the number of blocks until the key may try again.
+    pub fn charge(self, info: &RateInfo, height: u64, cost: u64) -> Result<RateInfo, u64> {
+        if cost == 0 {
+            return Ok(info.clone());
+        }
+        match self {
+            Rate::PerBlock(limit) => {
+                let (used, credit) = info.this_window(height);
+                let from_credit = credit.min(cost);
+                match used.checked_add(cost - from_credit) {
+                    Some(used) if used <= limit => Ok(RateInfo {
+                        last_updated_height: height,
+                        this_block: used,
+                        credit: credit - from_credit,
+                    }),
+                    _ => Err(1),
+                }
+            }
+            Rate::Blocks(blocks) => {
+                let window_end = info.blocks_window_end(blocks);
+                if window_end > height {
+                    Err(window_end - height)
+                } else {
+                    Ok(RateInfo {
+                        last_updated_height: height,
+                        this_block: cost,
+                        credit: 0,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Credits UNITS of inbound flow to a key with state INFO at
+    /// HEIGHT, returning its new state. Under `PerBlock`, units first
+    /// offset those used this block and the rest may be used later in
+    /// the block. Under `Blocks`, units shorten the key's open window
+    /// by one window each, and closing it entirely allows the key to
+    /// be limited immediately. Credit is never carried into a later
+    /// window.
+    pub fn credit(self, info: &RateInfo, height: u64, units: u64) -> RateInfo {
+        match self {
+            Rate::PerBlock(_) => {
+                let (used, credit) = info.this_window(height);
+                let offset = used.min(units);
+                RateInfo {
+                    last_updated_height: height,
+                    this_block: used - offset,
+                    credit: credit.saturating_add(units - offset),
+                }
+            }
+            Rate::Blocks(blocks) => {
+                if info.blocks_window_end(blocks) <= height {
+                    return info.clone();
+                }
+                let charged = info.this_block.max(1);
+                if units >= charged {
+                    RateInfo {
+                        last_updated_height: height.saturating_sub(blocks),
+                        this_block: 0,
+                        credit: 0,
+                    }
+                } else {
+                    RateInfo {
+                        this_block: charged - units,
+                        ..info.clone()
+                    }
+                }
+            }
+        }
+    }
+
     pub fn is_zero(self) -> bool {
         match self {
             Self::Blocks(_) => false,
@@ -153,6 +873,8 @@ impl Ord for Rate {
 
 #[cfg(test)]
 mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
     use super::*;
 
     #[test]
@@ -180,4 +902,287 @@ mod tests {
         assert!(zero.is_zero());
         assert!(zero < Rate::Blocks(!0));
     }
+
+    #[test]
+    fn test_snapshots() {
+        let limiter = RateLimiter::new_snapshotted("rl", "r", "c", "rlc", "rc");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(2)).unwrap();
+
+        let start = env.block.height;
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "a").unwrap();
+        env.block.height += 3;
+        limiter.limit(storage, &env, "a").unwrap();
+
+        // No state before the first limit.
+        assert_eq!(
+            limiter
+                .query_rate_info_at_height(storage, "a", start)
+                .unwrap(),
+            None
+        );
+        // Both limits in the first block are visible from the next.
+        for height in start + 1..=start + 3 {
+            assert_eq!(
+                limiter
+                    .query_rate_info_at_height(storage, "a", height)
+                    .unwrap(),
+                Some(RateInfo {
+                    last_updated_height: start,
+                    this_block: 2,
+                    credit: 0,
+                })
+            );
+        }
+        assert_eq!(
+            limiter
+                .query_rate_info_at_height(storage, "a", start + 4)
+                .unwrap(),
+            limiter.query_rate_info(storage, "a").unwrap()
+        );
+        assert_eq!(
+            limiter.query_limit_at_height(storage, start).unwrap(),
+            Rate::PerBlock(2)
+        );
+    }
+
+    #[test]
+    fn test_snapshots_disabled() {
+        let limiter = RateLimiter::new("rl", "r");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(2)).unwrap();
+        limiter.limit(storage, &env, "a").unwrap();
+
+        limiter
+            .query_rate_info_at_height(storage, "a", env.block.height)
+            .unwrap_err();
+        limiter
+            .query_limit_at_height(storage, env.block.height)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_reset() {
+        let limiter = RateLimiter::new("rl", "r");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        limiter.init(storage, &Rate::Blocks(100)).unwrap();
+
+        env.block.height = 200;
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "b").unwrap();
+        limiter.limit(storage, &env, "a").unwrap_err();
+
+        let event = limiter.reset(storage, &env, "a").unwrap();
+        assert_eq!(
+            event,
+            Event::new("rate_limit_reset").add_attribute("key", "a")
+        );
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "b").unwrap_err();
+    }
+
+    #[test]
+    fn test_limit_many() {
+        let limiter = RateLimiter::new("rl", "r").with_stats("ks", "ts");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(3)).unwrap();
+
+        limiter
+            .limit_many(storage, &env, &[("a", 2), ("b", 1), ("a", 1)])
+            .unwrap();
+        assert_eq!(
+            limiter
+                .query_rate_info(storage, "a")
+                .unwrap()
+                .unwrap()
+                .this_block,
+            3
+        );
+
+        // b has room but a does not, so neither is charged.
+        let err = limiter
+            .limit_many(storage, &env, &[("b", 1), ("a", 1)])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RateLimitError::Limited {
+                key: "a".to_string(),
+                blocks_remaining: 1
+            }
+        );
+        assert_eq!(
+            limiter
+                .query_rate_info(storage, "b")
+                .unwrap()
+                .unwrap()
+                .this_block,
+            1
+        );
+        // Each call counts once per key, whether or not it was the
+        // one that failed.
+        assert_eq!(
+            limiter.query_total_stats(storage).unwrap(),
+            TotalStats {
+                accepted: 2,
+                rejected: 2,
+                keys: 2,
+            }
+        );
+
+        env.block.height += 1;
+        limiter.limit_many(storage, &env, &[("a", 3)]).unwrap();
+        limiter.limit_many(storage, &env, &[("b", 4)]).unwrap_err();
+    }
+
+    #[test]
+    fn test_limit_many_new_key_limited() {
+        let limiter = RateLimiter::new("rl", "r")
+            .with_stats("ks", "ts")
+            .with_new_key_limit("nkl", "nkw");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(3)).unwrap();
+        limiter
+            .set_new_key_limit(
+                storage,
+                Some(&NewKeyLimit {
+                    max_new_keys: 1,
+                    window_blocks: 10,
+                }),
+            )
+            .unwrap();
+
+        let err = limiter
+            .limit_many(storage, &env, &[("a", 1), ("b", 1)])
+            .unwrap_err();
+        assert!(matches!(err, RateLimitError::NewKeyLimited { .. }));
+        assert_eq!(limiter.query_rate_info(storage, "a").unwrap(), None);
+        assert_eq!(
+            limiter
+                .query_key_stats(storage, "b")
+                .unwrap()
+                .unwrap()
+                .rejected,
+            1
+        );
+    }
+
+    #[test]
+    fn test_blocks_cost() {
+        let limiter = RateLimiter::new("rl", "r");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        limiter.init(storage, &Rate::Blocks(5)).unwrap();
+
+        limiter.limit_many(storage, &env, &[("a", 3)]).unwrap();
+        env.block.height = 114;
+        assert_eq!(
+            limiter.limit(storage, &env, "a").unwrap_err(),
+            RateLimitError::Limited {
+                key: "a".to_string(),
+                blocks_remaining: 1
+            }
+        );
+        env.block.height = 115;
+        limiter.limit(storage, &env, "a").unwrap();
+    }
+
+    #[test]
+    fn test_charge() {
+        let info = RateInfo {
+            last_updated_height: 10,
+            this_block: 2,
+            credit: 0,
+        };
+        assert_eq!(
+            Rate::PerBlock(3).charge(&info, 10, 1),
+            Ok(RateInfo {
+                last_updated_height: 10,
+                this_block: 3,
+                credit: 0,
+            })
+        );
+        assert_eq!(Rate::PerBlock(3).charge(&info, 10, 2), Err(1));
+        assert_eq!(Rate::PerBlock(3).charge(&info, 10, 0), Ok(info.clone()));
+        assert_eq!(Rate::PerBlock(0).charge(&info, 11, 1), Err(1));
+        assert_eq!(Rate::Blocks(4).charge(&info, 17, 1), Err(1));
+        assert_eq!(
+            Rate::Blocks(4).charge(&info, 18, 1),
+            Ok(RateInfo {
+                last_updated_height: 18,
+                this_block: 1,
+                credit: 0,
+            })
+        );
+        assert!(Rate::Blocks(0).charge(&info, 10, 5).is_ok());
+        // Keys that have never been charged are not limited, even
+        // before their first window could have ended.
+        assert!(Rate::Blocks(3).charge(&RateInfo::default(), 0, 1).is_ok());
+    }
+
+    #[test]
+    fn test_net_flow_per_block() {
+        let limiter = RateLimiter::new("rl", "r").with_net_flow();
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(2)).unwrap();
+
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "a").unwrap_err();
+        limiter.credit(storage, &env, "a", 3).unwrap();
+        // Two units offset and one banked for this block.
+        for _ in 0..3 {
+            limiter.limit(storage, &env, "a").unwrap();
+        }
+        limiter.limit(storage, &env, "a").unwrap_err();
+
+        limiter.credit(storage, &env, "a", 5).unwrap();
+        env.block.height += 1;
+        limiter.limit_many(storage, &env, &[("a", 2)]).unwrap();
+        limiter.limit(storage, &env, "a").unwrap_err();
+
+        // Unseen keys are not credited.
+        limiter.credit(storage, &env, "b", 5).unwrap();
+        assert_eq!(limiter.query_rate_info(storage, "b").unwrap(), None);
+    }
+
+    #[test]
+    fn test_net_flow_blocks() {
+        let limiter = RateLimiter::new("rl", "r").with_net_flow();
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        limiter.init(storage, &Rate::Blocks(10)).unwrap();
+
+        limiter.limit_many(storage, &env, &[("a", 3)]).unwrap();
+        env.block.height = 105;
+        limiter.credit(storage, &env, "a", 1).unwrap();
+        assert_eq!(
+            limiter.limit(storage, &env, "a").unwrap_err(),
+            RateLimitError::Limited {
+                key: "a".to_string(),
+                blocks_remaining: 15,
+            }
+        );
+        limiter.credit(storage, &env, "a", 2).unwrap();
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "a").unwrap_err();
+    }
+
+    #[test]
+    fn test_net_flow_disabled() {
+        let limiter = RateLimiter::new("rl", "r");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(2)).unwrap();
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.credit(storage, &env, "a", 1).unwrap_err();
+    }
 }
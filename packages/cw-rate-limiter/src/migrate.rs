@@ -0,0 +1,346 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Empty, Order, StdError, StdResult, Storage};
+use cw_storage_plus::{Bound, Item, Map};
+
+use crate::{RateInfo, RateLimiter};
+
+/// The layout of `RateInfo` entries written by this version of the
+/// package.
+///
+/// - 1: `{ last_updated_height, this_block }`, where `this_block` is
+///   always zero under `Rate::Blocks`.
+/// - 2: adds `credit`, and `this_block` holds the units charged in a
+///   `Rate::Blocks` window.
+///
+/// Every version is readable by the next, so keys may be limited
+/// while a migration is in progress.
+pub const STORAGE_VERSION: u64 = 2;
+
+/// The version of state that predates versioning.
+const UNVERSIONED: u64 = 1;
+
+#[cw_serde]
+pub struct MigrationStatus {
+    /// The version state was at before this batch.
+    pub from: u64,
+    /// The version state is being migrated to.
+    pub to: u64,
+    /// The number of entries rewritten by this batch.
+    pub migrated: u32,
+    /// True if no entries remain to be migrated and the stored
+    /// version is now `to`.
+    pub done: bool,
+}
+
+pub(crate) struct Version<'a, 'b> {
+    version: Item<'a, u64>,
+    cursor: Item<'a, String>,
+    // Keys a migration in progress has yet to reach that have since
+    // been written in the current layout, and so must not be
+    // upgraded.
+    written: Map<'a, &'b str, Empty>,
+}
+
+impl<'a> Version<'a, '_> {
+    pub(crate) const fn new(
+        version_key: &'a str,
+        cursor_key: &'a str,
+        written_key: &'a str,
+    ) -> Self {
+        Self {
+            version: Item::new(version_key),
+            cursor: Item::new(cursor_key),
+            written: Map::new(written_key),
+        }
+    }
+
+    pub(crate) fn init(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        self.version.save(storage, &STORAGE_VERSION)
+    }
+
+    /// Records that KEY has been written in the current layout, if a
+    /// migration is in progress and has yet to reach it.
+    pub(crate) fn record_write(&self, storage: &mut dyn Storage, key: &str) -> StdResult<()> {
+        if self.version.may_load(storage)?.unwrap_or(UNVERSIONED) >= STORAGE_VERSION {
+            return Ok(());
+        }
+        let reached = self
+            .cursor
+            .may_load(storage)?
+            .is_some_and(|cursor| key <= cursor.as_str());
+        if !reached {
+            self.written.save(storage, key, &Empty {})?;
+        }
+        Ok(())
+    }
+}
+
+/// Upgrades INFO, written at version FROM, to version FROM + 1.
+fn upgrade(from: u64, info: RateInfo) -> RateInfo {
+    match from {
+        // Version one stored zero for a key limited under
+        // `Rate::Blocks`. Keys that have been limited always have a
+        // non-zero height.
+        1 if info.this_block == 0 && info.last_updated_height != 0 => RateInfo {
+            this_block: 1,
+            ..info
+        },
+        _ => info,
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Records the layout of stored state under VERSION_KEY so that
+    /// it can be upgraded with `migrate_storage`. CURSOR_KEY holds the
+    /// progress of a migration between batches, and WRITTEN_KEY the
+    /// keys written since it started that it has yet to reach. While
+    /// a migration is in progress, this costs an additional read, and
+    /// up to one more read and write, each time a key's state is
+    /// saved.
+    pub const fn with_storage_version(
+        mut self,
+        version_key: &'a str,
+        cursor_key: &'a str,
+        written_key: &'a str,
+    ) -> Self {
+        self.version = Some(Version::new(version_key, cursor_key, written_key));
+        self
+    }
+
+    /// The layout version of stored state. State written before
+    /// versioning was enabled is version one. Errors if versioning is
+    /// not enabled.
+    pub fn storage_version(&self, storage: &dyn Storage) -> StdResult<u64> {
+        Ok(self
+            .version()?
+            .version
+            .may_load(storage)?
+            .unwrap_or(UNVERSIONED))
+    }
+
+    /// Upgrades at most LIMIT stored entries by one version, skipping
+    /// those written in the current layout since the migration
+    /// started. Intended
+    /// to be called from a contract's `migrate` entry point, and then
+    /// from later transactions until the returned status is `done`,
+    /// so that large states can be migrated without running out of
+    /// gas. Errors if versioning is not enabled or LIMIT is zero.
+    ///
+    /// Historical snapshots keep the layout they were written with.
+    pub fn migrate_storage(
+        &self,
+        storage: &mut dyn Storage,
+        limit: u32,
+    ) -> StdResult<MigrationStatus> {
+        if limit == 0 {
+            return Err(StdError::generic_err("migration batch must be non-zero"));
+        }
+        let version = self.version()?;
+        let from = self.storage_version(storage)?;
+        if from >= STORAGE_VERSION {
+            return Ok(MigrationStatus {
+                from,
+                to: from,
+                migrated: 0,
+                done: true,
+            });
+        }
+
+        let cursor = version.cursor.may_load(storage)?;
+        let start = cursor.as_deref().map(Bound::exclusive);
+        let batch = self
+            .rates
+            .range(storage, start, None, Order::Ascending)
+            .take(limit as usize + 1)
+            .collect::<StdResult<Vec<_>>>()?;
+        let done = batch.len() <= limit as usize;
+        let batch = &batch[..batch.len().min(limit as usize)];
+
+        let mut migrated = 0;
+        for (key, info) in batch {
+            if version.written.has(storage, key) {
+                version.written.remove(storage, key);
+            } else {
+                self.rates
+                    .save(storage, key, &upgrade(from, info.clone()))?;
+                migrated += 1;
+            }
+        }
+        let to = from + 1;
+        if done {
+            version.cursor.remove(storage);
+            version.version.save(storage, &to)?;
+        } else if let Some((key, _)) = batch.last() {
+            version.cursor.save(storage, key)?;
+        }
+        Ok(MigrationStatus {
+            from,
+            to,
+            migrated,
+            done: done && to == STORAGE_VERSION,
+        })
+    }
+
+    fn version(&self) -> StdResult<&Version<'a, 'b>> {
+        self.version
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not have a storage version"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+    use cw_storage_plus::Map;
+
+    use crate::Rate;
+
+    use super::*;
+
+    const LIMITER: RateLimiter =
+        RateLimiter::new("rl", "r").with_storage_version("version", "cursor", "written");
+
+    #[cw_serde]
+    struct RateInfoV1 {
+        last_updated_height: u64,
+        this_block: u64,
+    }
+
+    #[test]
+    fn test_migrate() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        let rates: Map<&str, RateInfoV1> = Map::new("r");
+        // State written before versioning under `Rate::Blocks(10)`.
+        Item::new("rl").save(storage, &Rate::Blocks(10)).unwrap();
+        for (key, height) in [("a", 100), ("b", 105), ("c", 110)] {
+            rates
+                .save(
+                    storage,
+                    key,
+                    &RateInfoV1 {
+                        last_updated_height: height,
+                        this_block: 0,
+                    },
+                )
+                .unwrap();
+        }
+        assert_eq!(LIMITER.storage_version(storage).unwrap(), 1);
+
+        let status = LIMITER.migrate_storage(storage, 2).unwrap();
+        assert_eq!(
+            status,
+            MigrationStatus {
+                from: 1,
+                to: 2,
+                migrated: 2,
+                done: false,
+            }
+        );
+        assert_eq!(LIMITER.storage_version(storage).unwrap(), 1);
+
+        // Keys may be limited mid-migration.
+        env.block.height = 112;
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "c").unwrap_err();
+
+        let status = LIMITER.migrate_storage(storage, 2).unwrap();
+        assert_eq!(
+            status,
+            MigrationStatus {
+                from: 1,
+                to: 2,
+                migrated: 1,
+                done: true,
+            }
+        );
+        assert_eq!(LIMITER.storage_version(storage).unwrap(), STORAGE_VERSION);
+        assert_eq!(
+            LIMITER.query_rate_info(storage, "b").unwrap(),
+            Some(RateInfo {
+                last_updated_height: 105,
+                this_block: 1,
+                credit: 0,
+            })
+        );
+        assert_eq!(
+            LIMITER.migrate_storage(storage, 2).unwrap(),
+            MigrationStatus {
+                from: STORAGE_VERSION,
+                to: STORAGE_VERSION,
+                migrated: 0,
+                done: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_migrate_skips_written() {
+        let limiter = LIMITER.with_net_flow();
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        let rates: Map<&str, RateInfoV1> = Map::new("r");
+        Item::new("rl").save(storage, &Rate::Blocks(10)).unwrap();
+        for (key, height) in [("a", 100), ("b", 105), ("c", 110)] {
+            rates
+                .save(
+                    storage,
+                    key,
+                    &RateInfoV1 {
+                        last_updated_height: height,
+                        this_block: 0,
+                    },
+                )
+                .unwrap();
+        }
+        limiter.migrate_storage(storage, 1).unwrap();
+
+        // Closing its window leaves zero in `this_block`, which is
+        // already in the current layout.
+        env.block.height = 112;
+        limiter.credit(storage, &env, "c", 1).unwrap();
+        let credited = RateInfo {
+            last_updated_height: 102,
+            this_block: 0,
+            credit: 0,
+        };
+        assert_eq!(
+            limiter.query_rate_info(storage, "c").unwrap(),
+            Some(credited.clone())
+        );
+
+        let status = limiter.migrate_storage(storage, 2).unwrap();
+        assert_eq!(status.migrated, 1);
+        assert!(status.done);
+        assert_eq!(
+            limiter.query_rate_info(storage, "c").unwrap(),
+            Some(credited)
+        );
+        assert_eq!(
+            limiter
+                .query_rate_info(storage, "b")
+                .unwrap()
+                .unwrap()
+                .this_block,
+            1
+        );
+        assert!(!Map::<&str, Empty>::new("written").has(storage, "c"));
+
+        // Nothing is recorded once migrated.
+        limiter.limit(storage, &env, "d").unwrap();
+        assert!(!Map::<&str, Empty>::new("written").has(storage, "d"));
+    }
+
+    #[test]
+    fn test_init_is_current() {
+        let storage = &mut MockStorage::default();
+        LIMITER.init(storage, &Rate::PerBlock(1)).unwrap();
+        assert_eq!(LIMITER.storage_version(storage).unwrap(), STORAGE_VERSION);
+        assert!(LIMITER.migrate_storage(storage, 1).unwrap().done);
+        LIMITER.migrate_storage(storage, 0).unwrap_err();
+
+        RateLimiter::new("rl", "r")
+            .storage_version(storage)
+            .unwrap_err();
+    }
+}
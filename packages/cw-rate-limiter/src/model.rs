@@ -0,0 +1,147 @@
+//! An executable reference model of `Rate` semantics, and a
+//! differential test that checks `RateLimiter` against it.
+//!
+//! The model keeps the full history of every key and answers from
+//! first principles, so it shares no code with the limiter.
+
+use std::collections::HashMap;
+
+use crate::Rate;
+
+#[derive(Default)]
+pub(crate) struct Model {
+    /// The heights at which each key was limited, in order.
+    history: HashMap<String, Vec<u64>>,
+}
+
+impl Model {
+    /// Limits KEY at HEIGHT under RATE, returning the number of
+    /// blocks until it may try again if it is over the limit.
+    ///
+    /// - `PerBlock(n)` allows at most `n` calls per key in each
+    ///   block. `PerBlock(0)` allows none.
+    /// - `Blocks(b)` allows a call if the key has never been limited
+    ///   or its last call was at least `b` blocks ago. `Blocks(0)`
+    ///   allows every call.
+    pub(crate) fn limit(&mut self, rate: Rate, height: u64, key: &str) -> Result<(), u64> {
+        let history = self.history.entry(key.to_string()).or_default();
+        match rate {
+            Rate::PerBlock(limit) => {
+                let this_block = history.iter().filter(|h| **h == height).count() as u64;
+                if this_block >= limit {
+                    return Err(1);
+                }
+            }
+            Rate::Blocks(blocks) => {
+                if let Some(last) = history.last() {
+                    if height < last + blocks {
+                        return Err(last + blocks - height);
+                    }
+                }
+            }
+        }
+        history.push(height);
+        Ok(())
+    }
+
+    /// Forgets KEY's history, as `RateLimiter::reset` does.
+    pub(crate) fn reset(&mut self, key: &str) {
+        self.history.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use crate::{RateLimitError, RateLimiter};
+
+    use super::*;
+
+    const TRACES: usize = 500;
+    const TRACE_LENGTH: usize = 200;
+    const KEYS: [&str; 4] = ["a", "b", "c", "d"];
+
+    fn random_rate(rng: &mut StdRng) -> Rate {
+        // Small values so that traces spend most of their time near
+        // the limit, including the `PerBlock(0)` and `Blocks(0)`
+        // edge cases.
+        match rng.gen() {
+            true => Rate::Blocks(rng.gen_range(0..6)),
+            false => Rate::PerBlock(rng.gen_range(0..4)),
+        }
+    }
+
+    /// Runs a random trace of calls against both the model and a
+    /// `RateLimiter`, panicking on the first disagreement.
+    fn run_trace(seed: u64) {
+        let rng = &mut StdRng::seed_from_u64(seed);
+        let limiter = RateLimiter::new("rate_limit", "rates");
+        let storage = &mut MockStorage::default();
+        let mut model = Model::default();
+        let mut env = mock_env();
+
+        let rate = random_rate(rng);
+        limiter.init(storage, &rate).unwrap();
+        // Start near genesis so windows that would end before height
+        // zero are covered.
+        env.block.height = rng.gen_range(0..5);
+
+        for step in 0..TRACE_LENGTH {
+            // Stay on the same block about half the time so that
+            // `PerBlock` limits are reached.
+            if rng.gen() {
+                env.block.height += rng.gen_range(1..4);
+            }
+            let key = KEYS[rng.gen_range(0..KEYS.len())];
+            let context = || {
+                format!(
+                    "seed {seed}, step {step}: {rate:?} at height {} for key {key}",
+                    env.block.height
+                )
+            };
+
+            if rng.gen_ratio(1, 20) {
+                limiter.reset(storage, &env, key).unwrap();
+                model.reset(key);
+                continue;
+            }
+
+            let expected = model.limit(rate, env.block.height, key);
+            let actual = limiter.limit(storage, &env, key).map_err(|e| match e {
+                RateLimitError::Limited {
+                    blocks_remaining, ..
+                } => blocks_remaining,
+                e => panic!("{}: unexpected error {e}", context()),
+            });
+            assert_eq!(actual, expected, "{}", context());
+        }
+    }
+
+    #[test]
+    fn test_model() {
+        let mut model = Model::default();
+        assert_eq!(model.limit(Rate::PerBlock(0), 1, "a"), Err(1));
+        assert_eq!(model.limit(Rate::Blocks(0), 1, "a"), Ok(()));
+        assert_eq!(model.limit(Rate::Blocks(0), 1, "a"), Ok(()));
+        assert_eq!(model.limit(Rate::Blocks(3), 2, "a"), Err(2));
+        assert_eq!(model.limit(Rate::Blocks(3), 2, "b"), Ok(()));
+        model.reset("a");
+        assert_eq!(model.limit(Rate::Blocks(3), 2, "a"), Ok(()));
+    }
+
+    /// Runs traces from fixed seeds so that failures are reproducible.
+    /// Set `RATE_LIMITER_SEED` to run a different set of traces, or to
+    /// the seed in a failure message to rerun that trace first.
+    #[test]
+    fn test_differential() {
+        let seed: u64 = match std::env::var("RATE_LIMITER_SEED") {
+            Ok(seed) => seed.parse().expect("RATE_LIMITER_SEED must be a u64"),
+            Err(_) => 0,
+        };
+        for trace in 0..TRACES as u64 {
+            run_trace(seed.wrapping_add(trace));
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{StdError, StdResult, Storage};
+use cw_storage_plus::Item;
+
+use crate::{RateLimitError, RateLimiter};
+
+/// Limits how many keys the rate limiter has never seen before may be
+/// admitted in a window. Without this, every new key starts with a
+/// full quota, so creating many keys gets around the per-key rate.
+#[cw_serde]
+pub struct NewKeyLimit {
+    /// Maximum number of new keys admitted per window.
+    pub max_new_keys: u64,
+    /// Length of a window in blocks. Windows are aligned to
+    /// multiples of this value. Must be non-zero.
+    pub window_blocks: u64,
+}
+
+#[cw_serde]
+#[derive(Default)]
+struct NewKeyWindow {
+    window_start: u64,
+    admitted: u64,
+}
+
+pub(crate) struct NewKeys<'a> {
+    limit: Item<'a, NewKeyLimit>,
+    window: Item<'a, NewKeyWindow>,
+}
+
+impl<'a> NewKeys<'a> {
+    pub(crate) const fn new(limit_key: &'a str, window_key: &'a str) -> Self {
+        Self {
+            limit: Item::new(limit_key),
+            window: Item::new(window_key),
+        }
+    }
+
+    /// Counts KEYS against the current window, erroring with the
+    /// first key that does not fit if the window would overflow. Does
+    /// nothing if no limit has been set.
+    pub(crate) fn admit(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        keys: &[&str],
+    ) -> Result<(), RateLimitError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let NewKeyLimit {
+            max_new_keys,
+            window_blocks,
+        } = match self.limit.may_load(storage)? {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let window_start = height - height % window_blocks;
+        let mut window = self
+            .window
+            .may_load(storage)?
+            .filter(|w| w.window_start == window_start)
+            .unwrap_or(NewKeyWindow {
+                window_start,
+                admitted: 0,
+            });
+        let room = max_new_keys.saturating_sub(window.admitted);
+        if let Some(key) = keys.get(room as usize) {
+            return Err(RateLimitError::NewKeyLimited {
+                key: key.to_string(),
+                blocks_remaining: window_start + window_blocks - height,
+            });
+        }
+        window.admitted += keys.len() as u64;
+        self.window.save(storage, &window)?;
+        Ok(())
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables a cap on the number of new keys admitted per window,
+    /// stored under LIMIT_KEY and WINDOW_KEY. The cap only applies
+    /// once it has been set with `set_new_key_limit`.
+    pub const fn with_new_key_limit(mut self, limit_key: &'a str, window_key: &'a str) -> Self {
+        self.new_keys = Some(NewKeys::new(limit_key, window_key));
+        self
+    }
+
+    /// Sets the cap on new keys per window, or removes it if LIMIT is
+    /// `None`. Errors if the cap is not enabled.
+    pub fn set_new_key_limit(
+        &self,
+        storage: &mut dyn Storage,
+        limit: Option<&NewKeyLimit>,
+    ) -> StdResult<()> {
+        let new_keys = self.new_keys()?;
+        match limit {
+            Some(limit) if limit.window_blocks == 0 => {
+                Err(StdError::generic_err("new key window must be non-zero"))
+            }
+            Some(limit) => new_keys.limit.save(storage, limit),
+            None => {
+                new_keys.limit.remove(storage);
+                Ok(())
+            }
+        }
+    }
+
+    /// Gets the cap on new keys per window, if one is set.
+    pub fn query_new_key_limit(&self, storage: &dyn Storage) -> StdResult<Option<NewKeyLimit>> {
+        self.new_keys()?.limit.may_load(storage)
+    }
+
+    fn new_keys(&self) -> StdResult<&NewKeys<'a>> {
+        self.new_keys
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not limit new keys"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::Rate;
+
+    use super::*;
+
+    #[test]
+    fn test_new_key_limit() {
+        let limiter = RateLimiter::new("rl", "r").with_new_key_limit("nkl", "nkw");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        limiter.init(storage, &Rate::PerBlock(1)).unwrap();
+        limiter
+            .set_new_key_limit(
+                storage,
+                Some(&NewKeyLimit {
+                    max_new_keys: 2,
+                    window_blocks: 10,
+                }),
+            )
+            .unwrap();
+
+        limiter.limit(storage, &env, "a").unwrap();
+        env.block.height += 1;
+        limiter.limit(storage, &env, "b").unwrap();
+        env.block.height += 1;
+        let err = limiter.limit(storage, &env, "c").unwrap_err();
+        assert_eq!(
+            err,
+            RateLimitError::NewKeyLimited {
+                key: "c".to_string(),
+                blocks_remaining: 8,
+            }
+        );
+        // Keys that have been seen are only subject to their own rate.
+        limiter.limit(storage, &env, "a").unwrap();
+
+        env.block.height = 110;
+        limiter.limit(storage, &env, "c").unwrap();
+    }
+
+    #[test]
+    fn test_new_key_limit_unset() {
+        let limiter = RateLimiter::new("rl", "r").with_new_key_limit("nkl", "nkw");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(1)).unwrap();
+        for key in ["a", "b", "c"] {
+            limiter.limit(storage, &env, key).unwrap();
+        }
+
+        limiter
+            .set_new_key_limit(
+                storage,
+                Some(&NewKeyLimit {
+                    max_new_keys: 1,
+                    window_blocks: 0,
+                }),
+            )
+            .unwrap_err();
+        assert_eq!(limiter.query_new_key_limit(storage).unwrap(), None);
+    }
+}
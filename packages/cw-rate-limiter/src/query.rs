@@ -0,0 +1,424 @@
+use cosmwasm_schema::{cw_serde, QueryResponses};
+use cosmwasm_std::{to_binary, Binary, BlockInfo, Env, Order, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+use crate::update::Effective;
+use crate::{
+    AdaptiveRate, AdaptiveRateState, Boost, BoostedKey, Bypass, GlobalLimit, GlobalUsage,
+    GraceAllowance, KeyStats, NewKeyLimit, Rate, RateInfo, RateLimiter, RateUsage, StateExport,
+    ThrottledKey, TotalStats, Tripwire, TripwireState, VoucherAuthority,
+};
+
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+
+/// Queries answered by `RateLimiter::handle_query`. Contracts may
+/// embed this in their own query message, either as a variant of an
+/// enum with `#[serde(untagged)]` and `#[query_responses(nested)]`,
+/// or by forwarding a nested variant.
+#[cw_serde]
+#[derive(QueryResponses)]
+pub enum RateLimiterQueryMsg {
+    /// Gets the rate limiter's configuration.
+    #[returns(RateLimiterConfig)]
+    Config {},
+
+    /// Gets the rate limiting state of a key.
+    #[returns(KeyState)]
+    KeyState { key: String },
+
+    /// Lists the rate limiting state of every key that has been
+    /// limited, in ascending order of key.
+    #[returns(Vec<KeyState>)]
+    Keys {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    /// Exports the limiter's configuration and a page of stored key
+    /// state, for importing into another contract with
+    /// `RateLimiter::import` or for analysis off-chain.
+    #[returns(StateExport)]
+    Export {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    /// Lists keys that have used all of their quota, in order of the
+    /// height at which they become available again. Errors if the
+    /// throttled key index is not enabled.
+    #[returns(Vec<ThrottledKey>)]
+    Throttled {
+        start_after: Option<ThrottledKey>,
+        limit: Option<u32>,
+    },
+
+    /// Gets the tripwire's configuration and whether the limiter is
+    /// tripped. Errors if the tripwire is not enabled.
+    #[returns(TripwireState)]
+    Tripwire {},
+
+    /// Gets the adaptive rate's configuration and the rate it
+    /// currently sets. Errors if adaptive rates are not enabled.
+    #[returns(AdaptiveRateState)]
+    AdaptiveRate {},
+
+    /// Gets a key's boost, or null if it does not have one that is
+    /// pending or in effect. Errors if boosts are not enabled.
+    #[returns(Option<Boost>)]
+    Boost { key: String },
+
+    /// Lists keys with a boost that is pending or in effect, in
+    /// ascending order of key. Errors if boosts are not enabled.
+    #[returns(Vec<BoostedKey>)]
+    Boosts {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    /// Gets the use of the global limit's shared and reserved pools
+    /// this window. Errors if the global limit is not enabled.
+    #[returns(GlobalUsage)]
+    GlobalUsage {},
+
+    /// Lists keys that may use the global limit's reserved pool, in
+    /// ascending order. Errors if the global limit is not enabled.
+    #[returns(Vec<String>)]
+    PriorityKeys {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+
+    /// Gets the public key vouchers must be signed with, or null if
+    /// vouchers are not being accepted. Errors if vouchers are not
+    /// enabled.
+    #[returns(Option<VoucherAuthority>)]
+    VoucherAuthority {},
+
+    /// Lists the units a key may use beyond its limit, in the order
+    /// they will be used. Errors if vouchers are not enabled.
+    #[returns(Vec<Bypass>)]
+    Bypass { key: String },
+
+    /// Returns true if the voucher with a nonce has been redeemed.
+    /// Errors if vouchers are not enabled.
+    #[returns(bool)]
+    VoucherUsed { nonce: u64 },
+
+    /// Gets the lifetime usage counters for a key, or null if it has
+    /// never been limited. Errors if stats are not enabled.
+    #[returns(Option<KeyStats>)]
+    KeyStats { key: String },
+
+    /// Gets the lifetime usage counters summed over all keys. Errors
+    /// if stats are not enabled.
+    #[returns(TotalStats)]
+    TotalStats {},
+}
+
+#[cw_serde]
+pub struct RateLimiterConfig {
+    pub rate_limit: Rate,
+    /// The cap on new keys per window, if one is set.
+    pub new_key_limit: Option<NewKeyLimit>,
+    /// The tripwire's threshold, if one is set.
+    pub tripwire: Option<Tripwire>,
+    /// The adaptive rate, if one is set. While set, it replaces
+    /// `rate_limit`.
+    pub adaptive_rate: Option<AdaptiveRate>,
+    /// The global limit, if one is set.
+    pub global_limit: Option<GlobalLimit>,
+    /// The grace allowance for new keys, if one is set.
+    pub grace_allowance: Option<GraceAllowance>,
+}
+
+#[cw_serde]
+pub struct KeyState {
+    pub key: String,
+    /// The stored state for the key, or `None` if it has never been
+    /// limited.
+    pub info: Option<RateInfo>,
+    /// The key's use of its quota at the queried height.
+    pub usage: RateUsage,
+    /// Units of grace allowance the key may still use beyond its
+    /// limit. For keys that have never been limited, this is the
+    /// grace they would be given.
+    pub grace: u64,
+}
+
+impl RateLimiter<'_, '_> {
+    /// Answers a `RateLimiterQueryMsg`.
+    pub fn handle_query(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        msg: RateLimiterQueryMsg,
+    ) -> StdResult<Binary> {
+        match msg {
+            RateLimiterQueryMsg::Config {} => to_binary(&self.query_config(storage)?),
+            RateLimiterQueryMsg::KeyState { key } => {
+                to_binary(&self.query_key_state(storage, env, key)?)
+            }
+            RateLimiterQueryMsg::Keys { start_after, limit } => {
+                to_binary(&self.query_keys(storage, env, start_after, limit)?)
+            }
+            RateLimiterQueryMsg::Export { start_after, limit } => {
+                to_binary(&self.export(storage, start_after, page_limit(limit))?)
+            }
+            RateLimiterQueryMsg::Throttled { start_after, limit } => {
+                to_binary(&self.query_throttled(storage, env, start_after, page_limit(limit))?)
+            }
+            RateLimiterQueryMsg::Tripwire {} => to_binary(&self.query_tripwire(storage)?),
+            RateLimiterQueryMsg::AdaptiveRate {} => {
+                to_binary(&self.query_adaptive_rate(storage, env.block.height)?)
+            }
+            RateLimiterQueryMsg::Boost { key } => to_binary(&self.query_boost(storage, env, &key)?),
+            RateLimiterQueryMsg::Boosts { start_after, limit } => {
+                to_binary(&self.query_boosts(storage, env, start_after, page_limit(limit))?)
+            }
+            RateLimiterQueryMsg::GlobalUsage {} => {
+                to_binary(&self.query_global_usage(storage, env.block.height)?)
+            }
+            RateLimiterQueryMsg::PriorityKeys { start_after, limit } => {
+                to_binary(&self.query_priority_keys(storage, start_after, page_limit(limit))?)
+            }
+            RateLimiterQueryMsg::VoucherAuthority {} => {
+                to_binary(&self.query_voucher_authority(storage)?)
+            }
+            RateLimiterQueryMsg::Bypass { key } => {
+                to_binary(&self.query_bypass(storage, env.block.height, &key)?)
+            }
+            RateLimiterQueryMsg::VoucherUsed { nonce } => {
+                to_binary(&self.query_voucher_used(storage, nonce)?)
+            }
+            RateLimiterQueryMsg::KeyStats { key } => {
+                to_binary(&self.query_key_stats(storage, &key)?)
+            }
+            RateLimiterQueryMsg::TotalStats {} => to_binary(&self.query_total_stats(storage)?),
+        }
+    }
+
+    pub fn query_config(&self, storage: &dyn Storage) -> StdResult<RateLimiterConfig> {
+        Ok(RateLimiterConfig {
+            rate_limit: self.query_limit(storage)?,
+            new_key_limit: match &self.new_keys {
+                Some(_) => self.query_new_key_limit(storage)?,
+                None => None,
+            },
+            tripwire: match &self.tripwire {
+                Some(_) => self.query_tripwire(storage)?.tripwire,
+                None => None,
+            },
+            adaptive_rate: match &self.adaptive {
+                Some(adaptive) => adaptive.config(storage)?,
+                None => None,
+            },
+            global_limit: match &self.global {
+                Some(global) => global.config(storage)?,
+                None => None,
+            },
+            grace_allowance: match &self.grace {
+                Some(_) => self.query_grace_allowance(storage)?,
+                None => None,
+            },
+        })
+    }
+
+    pub fn query_key_state(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        key: String,
+    ) -> StdResult<KeyState> {
+        let info = self.query_rate_info(storage, &key)?;
+        self.key_state(storage, &env.block, key, info)
+    }
+
+    pub fn query_keys(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<Vec<KeyState>> {
+        self.rates
+            .range(
+                storage,
+                start_after.map(|s| Bound::ExclusiveRaw(s.into_bytes())),
+                None,
+                Order::Ascending,
+            )
+            .take(page_limit(limit))
+            .map(|item| {
+                let (key, info) = item?;
+                self.key_state(storage, &env.block, key, Some(info))
+            })
+            .collect()
+    }
+
+    fn key_state(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        key: String,
+        info: Option<RateInfo>,
+    ) -> StdResult<KeyState> {
+        let height = block.height;
+        let usage = self.usage(storage, block, &key, info.clone())?;
+        let grace = match &self.grace {
+            Some(grace) => grace.remaining(storage, height, &key, info.is_some())?,
+            None => 0,
+        };
+        Ok(KeyState {
+            key,
+            info,
+            usage,
+            grace,
+        })
+    }
+
+    // KEY's use of its quota in BLOCK, given its stored state INFO.
+    pub(crate) fn usage(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        key: &str,
+        info: Option<RateInfo>,
+    ) -> StdResult<RateUsage> {
+        let height = block.height;
+        let rate = self.current_rate(storage, height)?;
+        Ok(match self.effective(storage, block, key, rate, info)? {
+            Effective::Active(rate, info) => RateUsage::new(rate, &info, height),
+            Effective::BlockedUntil(until) => RateUsage {
+                used: 1,
+                remaining: 0,
+                window_end: until,
+            },
+        })
+    }
+}
+
+fn page_limit(limit: Option<u32>) -> usize {
+    limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::from_binary;
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use super::*;
+
+    #[test]
+    fn test_handle_query() {
+        let limiter = RateLimiter::new("rl", "r").with_stats("ks", "ts");
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        let height = env.block.height;
+        limiter.init(storage, &Rate::PerBlock(2)).unwrap();
+        for key in ["c", "a", "b", "a"] {
+            limiter.limit(storage, &env, key).unwrap();
+        }
+
+        let config: RateLimiterConfig = from_binary(
+            &limiter
+                .handle_query(storage, &env, RateLimiterQueryMsg::Config {})
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            RateLimiterConfig {
+                rate_limit: Rate::PerBlock(2),
+                new_key_limit: None,
+                tripwire: None,
+                adaptive_rate: None,
+                global_limit: None,
+                grace_allowance: None,
+            }
+        );
+
+        let state: KeyState = from_binary(
+            &limiter
+                .handle_query(
+                    storage,
+                    &env,
+                    RateLimiterQueryMsg::KeyState {
+                        key: "d".to_string(),
+                    },
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            state,
+            KeyState {
+                key: "d".to_string(),
+                info: None,
+                usage: RateUsage {
+                    used: 0,
+                    remaining: 2,
+                    window_end: height,
+                },
+                grace: 0,
+            }
+        );
+
+        let keys: Vec<KeyState> = from_binary(
+            &limiter
+                .handle_query(
+                    storage,
+                    &env,
+                    RateLimiterQueryMsg::Keys {
+                        start_after: Some("a".to_string()),
+                        limit: None,
+                    },
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            keys.iter().map(|k| k.key.as_str()).collect::<Vec<_>>(),
+            vec!["b", "c"]
+        );
+
+        let keys: Vec<KeyState> = from_binary(
+            &limiter
+                .handle_query(
+                    storage,
+                    &env,
+                    RateLimiterQueryMsg::Keys {
+                        start_after: None,
+                        limit: Some(1),
+                    },
+                )
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            keys,
+            vec![KeyState {
+                key: "a".to_string(),
+                info: Some(RateInfo {
+                    last_updated_height: height,
+                    this_block: 2,
+                    credit: 0,
+                }),
+                usage: RateUsage {
+                    used: 2,
+                    remaining: 0,
+                    window_end: height + 1,
+                },
+                grace: 0,
+            }]
+        );
+
+        let totals: TotalStats = from_binary(
+            &limiter
+                .handle_query(storage, &env, RateLimiterQueryMsg::TotalStats {})
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(totals.accepted, 4);
+    }
+}
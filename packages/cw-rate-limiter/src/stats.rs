@@ -0,0 +1,167 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{StdError, StdResult, Storage};
+use cw_storage_plus::{Item, Map};
+
+use crate::RateLimiter;
+
+/// Lifetime usage counters for a single key.
+#[cw_serde]
+#[derive(Default)]
+pub struct KeyStats {
+    /// Number of calls to `limit` that were allowed. A call to
+    /// `limit_many` counts once for each distinct key in it.
+    pub accepted: u64,
+    /// Number of calls to `limit` that were rate limited. This is
+    /// best-effort: the count is written before `limit` returns its
+    /// error, so it is only persisted if the caller handles the error
+    /// without reverting the transaction. A contract that propagates
+    /// the error (as most do) will never see this increase.
+    pub rejected: u64,
+    /// Height of the first call to `limit` for this key.
+    pub first_seen_height: u64,
+    /// Height of the most recent call to `limit` for this key.
+    pub last_seen_height: u64,
+}
+
+/// Lifetime usage counters summed over all keys.
+#[cw_serde]
+#[derive(Default)]
+pub struct TotalStats {
+    pub accepted: u64,
+    /// Best-effort, see `KeyStats::rejected`.
+    pub rejected: u64,
+    /// Number of distinct keys ever seen.
+    pub keys: u64,
+}
+
+pub(crate) struct Stats<'a, 'b> {
+    keys: Map<'a, &'b str, KeyStats>,
+    totals: Item<'a, TotalStats>,
+}
+
+impl<'a> Stats<'a, '_> {
+    pub(crate) const fn new(key_stats_key: &'a str, total_stats_key: &'a str) -> Self {
+        Self {
+            keys: Map::new(key_stats_key),
+            totals: Item::new(total_stats_key),
+        }
+    }
+
+    pub(crate) fn record(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        key: &str,
+        accepted: bool,
+    ) -> StdResult<()> {
+        let mut totals = self.totals.may_load(storage)?.unwrap_or_default();
+        let mut stats = match self.keys.may_load(storage, key)? {
+            Some(stats) => stats,
+            None => {
+                totals.keys += 1;
+                KeyStats {
+                    first_seen_height: height,
+                    ..Default::default()
+                }
+            }
+        };
+        stats.last_seen_height = height;
+        if accepted {
+            stats.accepted += 1;
+            totals.accepted += 1;
+        } else {
+            stats.rejected += 1;
+            totals.rejected += 1;
+        }
+        self.keys.save(storage, key, &stats)?;
+        self.totals.save(storage, &totals)
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables lifetime usage counters, stored under KEY_STATS_KEY and
+    /// TOTAL_STATS_KEY. This costs two additional reads and writes
+    /// per call to `limit`.
+    pub const fn with_stats(mut self, key_stats_key: &'a str, total_stats_key: &'a str) -> Self {
+        self.stats = Some(Stats::new(key_stats_key, total_stats_key));
+        self
+    }
+
+    /// Gets the lifetime usage counters for KEY, or `None` if KEY has
+    /// never been limited. Errors if stats are not enabled.
+    pub fn query_key_stats(&self, storage: &dyn Storage, key: &str) -> StdResult<Option<KeyStats>> {
+        self.stats()?.keys.may_load(storage, key)
+    }
+
+    /// Gets the lifetime usage counters summed over all keys. Errors
+    /// if stats are not enabled.
+    pub fn query_total_stats(&self, storage: &dyn Storage) -> StdResult<TotalStats> {
+        Ok(self.stats()?.totals.may_load(storage)?.unwrap_or_default())
+    }
+
+    fn stats(&self) -> StdResult<&Stats<'a, 'b>> {
+        self.stats
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not record stats"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::{Rate, RateLimiter};
+
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let limiter = RateLimiter::new("rl", "r").with_stats("ks", "ts");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        limiter.init(storage, &Rate::PerBlock(1)).unwrap();
+
+        let start = env.block.height;
+        limiter.limit(storage, &env, "a").unwrap();
+        limiter.limit(storage, &env, "a").unwrap_err();
+        limiter.limit(storage, &env, "b").unwrap();
+        env.block.height += 5;
+        limiter.limit(storage, &env, "a").unwrap();
+
+        assert_eq!(
+            limiter.query_key_stats(storage, "a").unwrap(),
+            Some(KeyStats {
+                accepted: 2,
+                rejected: 1,
+                first_seen_height: start,
+                last_seen_height: start + 5,
+            })
+        );
+        assert_eq!(
+            limiter.query_key_stats(storage, "b").unwrap(),
+            Some(KeyStats {
+                accepted: 1,
+                rejected: 0,
+                first_seen_height: start,
+                last_seen_height: start,
+            })
+        );
+        assert_eq!(limiter.query_key_stats(storage, "c").unwrap(), None);
+        assert_eq!(
+            limiter.query_total_stats(storage).unwrap(),
+            TotalStats {
+                accepted: 3,
+                rejected: 1,
+                keys: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn test_stats_disabled() {
+        let limiter = RateLimiter::new("rl", "r");
+        let storage = &mut MockStorage::default();
+        limiter.query_key_stats(storage, "a").unwrap_err();
+        limiter.query_total_stats(storage).unwrap_err();
+    }
+}
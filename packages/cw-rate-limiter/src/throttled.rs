@@ -0,0 +1,324 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Env, Order, StdError, StdResult, Storage};
+use cw_storage_plus::Map;
+
+use crate::{Rate, RateInfo, RateLimiter, RateUsage};
+
+/// A key that has used all of its quota.
+#[cw_serde]
+pub struct ThrottledKey {
+    pub key: String,
+    /// Height at which the key may next be limited.
+    pub available_at: u64,
+}
+
+// Keys at their limit, with the height they become available again
+// as of when their state was last saved. An entry is left in place
+// once it expires and replaced the next time the key's state is
+// saved. Entries also go stale when the rate, or a key's boost,
+// changes without its state being saved, so readers recompute each
+// key's availability from its current state.
+pub(crate) struct ThrottledIndex<'a, 'b> {
+    by_key: Map<'a, &'b str, u64>,
+}
+
+impl<'a> ThrottledIndex<'a, '_> {
+    pub(crate) const fn new(index_key: &'a str) -> Self {
+        Self {
+            by_key: Map::new(index_key),
+        }
+    }
+
+    /// Records that KEY, with state INFO under RATE at HEIGHT, is
+    /// throttled if it has no quota left, removing any previous
+    /// entry.
+    pub(crate) fn update(
+        &self,
+        storage: &mut dyn Storage,
+        rate: Rate,
+        key: &str,
+        info: &RateInfo,
+        height: u64,
+    ) -> StdResult<()> {
+        let usage = RateUsage::new(rate, info, height);
+        let available_at = (usage.remaining == 0).then_some(usage.window_end);
+        if self.by_key.may_load(storage, key)? == available_at {
+            return Ok(());
+        }
+        match available_at {
+            Some(available_at) => self.by_key.save(storage, key, &available_at),
+            None => {
+                self.by_key.remove(storage, key);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables an index of keys that have used all of their quota,
+    /// stored under INDEX_KEY. This costs up to one additional read
+    /// and one write each time a key's state is saved.
+    pub const fn with_throttled_index(mut self, index_key: &'a str) -> Self {
+        self.throttled = Some(ThrottledIndex::new(index_key));
+        self
+    }
+
+    /// Lists keys that have no quota left at the current height,
+    /// soonest available first, with the height at which each becomes
+    /// available again. Keys available at the same height are listed
+    /// in key order. Every key in the index is read, so the cost of
+    /// this grows with the number of keys that have ever been
+    /// throttled. Errors if the index is not enabled.
+    pub fn query_throttled(
+        &self,
+        storage: &dyn Storage,
+        env: &Env,
+        start_after: Option<ThrottledKey>,
+        limit: usize,
+    ) -> StdResult<Vec<ThrottledKey>> {
+        let index = self
+            .throttled
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not index throttled keys"))?;
+        let height = env.block.height;
+        let mut throttled = vec![];
+        for key in index.by_key.keys(storage, None, None, Order::Ascending) {
+            let key = key?;
+            let info = self.rates.may_load(storage, &key)?;
+            let usage = self.usage(storage, &env.block, &key, info)?;
+            if usage.remaining == 0 && usage.window_end > height {
+                throttled.push((usage.window_end, key));
+            }
+        }
+        throttled.sort_unstable();
+        let start = match &start_after {
+            Some(ThrottledKey { key, available_at }) => throttled
+                .partition_point(|(h, k)| (*h, k.as_str()) <= (*available_at, key.as_str())),
+            None => 0,
+        };
+        Ok(throttled
+            .into_iter()
+            .skip(start)
+            .take(limit)
+            .map(|(available_at, key)| ThrottledKey { key, available_at })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use cw_utils::Expiration;
+
+    use crate::RateTransition;
+
+    use super::*;
+
+    fn throttled(key: &str, available_at: u64) -> ThrottledKey {
+        ThrottledKey {
+            key: key.to_string(),
+            available_at,
+        }
+    }
+
+    fn at(height: u64) -> Env {
+        let mut env = mock_env();
+        env.block.height = height;
+        env
+    }
+
+    #[test]
+    fn test_throttled_index() {
+        let limiter = RateLimiter::new("rl", "r").with_throttled_index("t");
+        let storage = &mut MockStorage::default();
+        limiter.init(storage, &Rate::Blocks(10)).unwrap();
+
+        limiter.limit(storage, &at(100), "b").unwrap();
+        limiter.limit(storage, &at(103), "a").unwrap();
+        limiter.limit(storage, &at(105), "c").unwrap();
+
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(105), None, 10)
+                .unwrap(),
+            vec![
+                throttled("b", 110),
+                throttled("a", 113),
+                throttled("c", 115)
+            ]
+        );
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(105), Some(throttled("b", 110)), 1)
+                .unwrap(),
+            vec![throttled("a", 113)]
+        );
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(110), None, 10)
+                .unwrap(),
+            vec![throttled("a", 113), throttled("c", 115)]
+        );
+
+        limiter.reset(storage, &at(105), "a").unwrap();
+        limiter.limit(storage, &at(111), "b").unwrap();
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(111), None, 10)
+                .unwrap(),
+            vec![throttled("c", 115), throttled("b", 121)]
+        );
+    }
+
+    #[test]
+    fn test_throttled_per_block() {
+        let limiter = RateLimiter::new("rl", "r")
+            .with_throttled_index("t")
+            .with_net_flow();
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        let height = env.block.height;
+        limiter.init(storage, &Rate::PerBlock(2)).unwrap();
+
+        limiter
+            .limit_many(storage, &env, &[("a", 1), ("b", 2)])
+            .unwrap();
+        assert_eq!(
+            limiter.query_throttled(storage, &env, None, 10).unwrap(),
+            vec![throttled("b", height + 1)]
+        );
+        limiter.limit(storage, &env, "a").unwrap();
+        assert_eq!(
+            limiter.query_throttled(storage, &env, None, 10).unwrap(),
+            vec![throttled("a", height + 1), throttled("b", height + 1)]
+        );
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(height + 1), None, 10)
+                .unwrap(),
+            vec![]
+        );
+
+        // Credit beyond what was used this block is still available.
+        limiter.credit(storage, &env, "b", 3).unwrap();
+        assert_eq!(
+            limiter.query_throttled(storage, &env, None, 10).unwrap(),
+            vec![throttled("a", height + 1)]
+        );
+    }
+
+    #[test]
+    fn test_throttled_after_rate_update() {
+        let limiter = RateLimiter::new("rl", "r")
+            .with_throttled_index("t")
+            .with_rate_updates("rc");
+        let storage = &mut MockStorage::default();
+        limiter.init(storage, &Rate::Blocks(10)).unwrap();
+        limiter.limit(storage, &at(100), "a").unwrap();
+        limiter.limit(storage, &at(100), "b").unwrap();
+
+        limiter
+            .update_rate(
+                storage,
+                &at(100),
+                Rate::Blocks(4),
+                RateTransition::Proportional,
+            )
+            .unwrap();
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(101), None, 10)
+                .unwrap(),
+            vec![throttled("a", 105), throttled("b", 105)]
+        );
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(101), Some(throttled("a", 105)), 10)
+                .unwrap(),
+            vec![throttled("b", 105)]
+        );
+
+        let storage = &mut MockStorage::default();
+        limiter.init(storage, &Rate::Blocks(10)).unwrap();
+        limiter.limit(storage, &at(100), "a").unwrap();
+        limiter
+            .update_rate(storage, &at(100), Rate::Blocks(4), RateTransition::Reset)
+            .unwrap();
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(101), None, 10)
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_throttled_after_longer_rate() {
+        let limiter = RateLimiter::new("rl", "r")
+            .with_throttled_index("t")
+            .with_rate_updates("rc");
+        let storage = &mut MockStorage::default();
+        limiter.init(storage, &Rate::Blocks(10)).unwrap();
+        limiter.limit(storage, &at(100), "a").unwrap();
+        limiter.limit(storage, &at(105), "b").unwrap();
+        limiter
+            .update_rate(
+                storage,
+                &at(105),
+                Rate::Blocks(100),
+                RateTransition::Proportional,
+            )
+            .unwrap();
+
+        // a's entry expired at 110, but it is still throttled.
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(108), None, 10)
+                .unwrap(),
+            vec![throttled("a", 146), throttled("b", 196)]
+        );
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(120), None, 10)
+                .unwrap(),
+            vec![throttled("a", 146), throttled("b", 196)]
+        );
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(120), Some(throttled("a", 146)), 10)
+                .unwrap(),
+            vec![throttled("b", 196)]
+        );
+    }
+
+    #[test]
+    fn test_throttled_boosted() {
+        let limiter = RateLimiter::new("rl", "r")
+            .with_throttled_index("t")
+            .with_boosts("b");
+        let storage = &mut MockStorage::default();
+        limiter.init(storage, &Rate::Blocks(10)).unwrap();
+        limiter.limit(storage, &at(100), "a").unwrap();
+        limiter.limit(storage, &at(103), "b").unwrap();
+        limiter
+            .boost(
+                storage,
+                &at(104),
+                "a",
+                Rate::Blocks(100),
+                Expiration::Never {},
+            )
+            .unwrap();
+
+        // Keys are listed by when they become available, not by the
+        // heights in the index.
+        assert_eq!(
+            limiter
+                .query_throttled(storage, &at(105), None, 10)
+                .unwrap(),
+            vec![throttled("b", 113), throttled("a", 200)]
+        );
+    }
+}
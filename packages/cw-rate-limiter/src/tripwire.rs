@@ -0,0 +1,299 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{StdError, StdResult, Storage};
+use cw_storage_plus::Item;
+
+use crate::{RateLimitError, RateLimiter};
+
+/// An emergency stop. If more than `threshold` units are limited
+/// across all keys within a window, the limiter trips and every
+/// call to `limit` fails until it is reset with `reset_tripwire`.
+#[cw_serde]
+pub struct Tripwire {
+    pub threshold: u64,
+    /// Length of a window in blocks. Windows are aligned to
+    /// multiples of this value. Must be non-zero.
+    pub window_blocks: u64,
+}
+
+#[cw_serde]
+pub enum TripCause {
+    /// Traffic in the window starting at `window_start` reached
+    /// `total` units when `key` was limited.
+    Threshold {
+        window_start: u64,
+        total: u64,
+        key: String,
+    },
+    /// Tripped by `RateLimiter::trip`.
+    Manual { reason: String },
+}
+
+#[cw_serde]
+pub struct Tripped {
+    pub height: u64,
+    pub cause: TripCause,
+}
+
+#[cw_serde]
+pub struct TripwireState {
+    /// The tripwire's configuration, if one is set.
+    pub tripwire: Option<Tripwire>,
+    /// When and why the limiter tripped, if it is tripped.
+    pub tripped: Option<Tripped>,
+}
+
+#[cw_serde]
+struct TripwireWindow {
+    window_start: u64,
+    total: u64,
+}
+
+pub(crate) struct TripwireStore<'a> {
+    tripwire: Item<'a, Tripwire>,
+    window: Item<'a, TripwireWindow>,
+    tripped: Item<'a, Tripped>,
+}
+
+impl<'a> TripwireStore<'a> {
+    pub(crate) const fn new(
+        tripwire_key: &'a str,
+        window_key: &'a str,
+        tripped_key: &'a str,
+    ) -> Self {
+        Self {
+            tripwire: Item::new(tripwire_key),
+            window: Item::new(window_key),
+            tripped: Item::new(tripped_key),
+        }
+    }
+
+    /// Errors if the limiter is tripped.
+    pub(crate) fn check(&self, storage: &dyn Storage) -> Result<(), RateLimitError> {
+        match self.tripped.may_load(storage)? {
+            Some(Tripped { height, .. }) => Err(RateLimitError::Tripped { height }),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds UNITS limited for KEY at HEIGHT to the current window,
+    /// tripping if this takes it over the threshold. The call that
+    /// trips the limiter is still allowed, as the error would revert
+    /// the trip along with it.
+    pub(crate) fn record(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        key: &str,
+        units: u64,
+    ) -> StdResult<()> {
+        let Tripwire {
+            threshold,
+            window_blocks,
+        } = match self.tripwire.may_load(storage)? {
+            Some(tripwire) => tripwire,
+            None => return Ok(()),
+        };
+        let window_start = height - height % window_blocks;
+        let mut window = self
+            .window
+            .may_load(storage)?
+            .filter(|w| w.window_start == window_start)
+            .unwrap_or(TripwireWindow {
+                window_start,
+                total: 0,
+            });
+        window.total = window.total.saturating_add(units);
+        self.window.save(storage, &window)?;
+        if window.total > threshold {
+            self.tripped.save(
+                storage,
+                &Tripped {
+                    height,
+                    cause: TripCause::Threshold {
+                        window_start,
+                        total: window.total,
+                        key: key.to_string(),
+                    },
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables a tripwire, stored under TRIPWIRE_KEY, WINDOW_KEY and
+    /// TRIPPED_KEY. Traffic only trips the limiter once a threshold
+    /// has been set with `set_tripwire`, but `trip` may be used at any
+    /// time.
+    pub const fn with_tripwire(
+        mut self,
+        tripwire_key: &'a str,
+        window_key: &'a str,
+        tripped_key: &'a str,
+    ) -> Self {
+        self.tripwire = Some(TripwireStore::new(tripwire_key, window_key, tripped_key));
+        self
+    }
+
+    /// Sets the tripwire's threshold, or removes it if TRIPWIRE is
+    /// `None`. Errors if the tripwire is not enabled.
+    pub fn set_tripwire(
+        &self,
+        storage: &mut dyn Storage,
+        tripwire: Option<&Tripwire>,
+    ) -> StdResult<()> {
+        let store = self.tripwire_store()?;
+        match tripwire {
+            Some(tripwire) if tripwire.window_blocks == 0 => {
+                Err(StdError::generic_err("tripwire window must be non-zero"))
+            }
+            Some(tripwire) => store.tripwire.save(storage, tripwire),
+            None => {
+                store.tripwire.remove(storage);
+                Ok(())
+            }
+        }
+    }
+
+    /// Trips the limiter at HEIGHT for REASON. Callers are responsible
+    /// for checking that the sender is authorized to do this.
+    pub fn trip(&self, storage: &mut dyn Storage, height: u64, reason: String) -> StdResult<()> {
+        self.tripwire_store()?.tripped.save(
+            storage,
+            &Tripped {
+                height,
+                cause: TripCause::Manual { reason },
+            },
+        )
+    }
+
+    /// Resets a tripped limiter, along with the traffic counted in
+    /// the current window. Callers are responsible for checking that
+    /// the sender is authorized to do this.
+    pub fn reset_tripwire(&self, storage: &mut dyn Storage) -> StdResult<()> {
+        let store = self.tripwire_store()?;
+        store.tripped.remove(storage);
+        store.window.remove(storage);
+        Ok(())
+    }
+
+    pub fn query_tripwire(&self, storage: &dyn Storage) -> StdResult<TripwireState> {
+        let store = self.tripwire_store()?;
+        Ok(TripwireState {
+            tripwire: store.tripwire.may_load(storage)?,
+            tripped: store.tripped.may_load(storage)?,
+        })
+    }
+
+    fn tripwire_store(&self) -> StdResult<&TripwireStore<'a>> {
+        self.tripwire
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not have a tripwire"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::Rate;
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r").with_tripwire("tw", "tww", "twt");
+
+    #[test]
+    fn test_tripwire() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::PerBlock(5)).unwrap();
+        LIMITER
+            .set_tripwire(
+                storage,
+                Some(&Tripwire {
+                    threshold: 4,
+                    window_blocks: 10,
+                }),
+            )
+            .unwrap();
+
+        LIMITER
+            .limit_many(storage, &env, &[("a", 2), ("b", 1)])
+            .unwrap();
+        env.block.height = 105;
+        LIMITER.limit(storage, &env, "c").unwrap();
+        // Goes over the threshold, but is still allowed.
+        LIMITER.limit(storage, &env, "d").unwrap();
+        assert_eq!(
+            LIMITER.limit(storage, &env, "e").unwrap_err(),
+            RateLimitError::Tripped { height: 105 }
+        );
+        env.block.height = 200;
+        LIMITER.limit(storage, &env, "e").unwrap_err();
+        assert_eq!(
+            LIMITER.query_tripwire(storage).unwrap().tripped,
+            Some(Tripped {
+                height: 105,
+                cause: TripCause::Threshold {
+                    window_start: 100,
+                    total: 5,
+                    key: "d".to_string(),
+                }
+            })
+        );
+
+        LIMITER.reset_tripwire(storage).unwrap();
+        LIMITER.limit(storage, &env, "e").unwrap();
+        assert_eq!(LIMITER.query_tripwire(storage).unwrap().tripped, None);
+    }
+
+    #[test]
+    fn test_window_rolls_over() {
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::PerBlock(5)).unwrap();
+        LIMITER
+            .set_tripwire(
+                storage,
+                Some(&Tripwire {
+                    threshold: 2,
+                    window_blocks: 10,
+                }),
+            )
+            .unwrap();
+        for height in [109, 110, 120, 139] {
+            env.block.height = height;
+            LIMITER.limit(storage, &env, "a").unwrap();
+            LIMITER.limit(storage, &env, "b").unwrap();
+        }
+        assert_eq!(LIMITER.query_tripwire(storage).unwrap().tripped, None);
+    }
+
+    #[test]
+    fn test_manual_trip() {
+        let storage = &mut MockStorage::default();
+        let env = mock_env();
+        LIMITER.init(storage, &Rate::PerBlock(5)).unwrap();
+        LIMITER
+            .trip(storage, env.block.height, "exploit".to_string())
+            .unwrap();
+        assert_eq!(
+            LIMITER.limit(storage, &env, "a").unwrap_err(),
+            RateLimitError::Tripped {
+                height: env.block.height
+            }
+        );
+        LIMITER
+            .set_tripwire(
+                storage,
+                Some(&Tripwire {
+                    threshold: 2,
+                    window_blocks: 0,
+                }),
+            )
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,400 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{Env, Order, StdError, StdResult, Storage};
+use cw_storage_plus::{Bound, Map};
+
+use crate::{Rate, RateInfo, RateLimiter};
+
+/// How usage recorded under the previous rate is treated once a new
+/// rate set by `RateLimiter::update_rate` takes effect.
+#[cw_serde]
+#[derive(Copy)]
+pub enum RateTransition {
+    /// Forget all usage. Every key starts the new rate with a full
+    /// quota.
+    Reset,
+    /// Carry the fraction of its window a key had used under the
+    /// previous rate over to its first window under the new rate. For
+    /// example, a key halfway through a `Blocks(10)` window when
+    /// moving to `Blocks(4)` must wait two more blocks.
+    Proportional,
+    /// Keys finish the window they were in under the previous rate,
+    /// and the new rate applies from their next window.
+    NextWindow,
+}
+
+#[cw_serde]
+struct RateChange {
+    /// The rate in effect before the change.
+    previous: Rate,
+    transition: RateTransition,
+}
+
+/// A key's state translated to the rate in effect at some height.
+pub(crate) enum Effective {
+    /// The key is limited under RATE with state INFO.
+    Active(Rate, RateInfo),
+    /// The key may not be limited until the given height as its
+    /// window under a previous rate has not yet ended.
+    BlockedUntil(u64),
+}
+
+// Rate changes keyed by the height they take effect at.
+pub(crate) struct RateChanges<'a> {
+    changes: Map<'a, u64, RateChange>,
+}
+
+impl<'a> RateChanges<'a> {
+    pub(crate) const fn new(changes_key: &'a str) -> Self {
+        Self {
+            changes: Map::new(changes_key),
+        }
+    }
+
+    // The first change taking effect in (after, until].
+    fn first_change(
+        &self,
+        storage: &dyn Storage,
+        after: u64,
+        until: Option<u64>,
+    ) -> StdResult<Option<(u64, RateChange)>> {
+        self.changes
+            .range(
+                storage,
+                Some(Bound::exclusive(after)),
+                until.map(Bound::inclusive),
+                Order::Ascending,
+            )
+            .next()
+            .transpose()
+    }
+
+    /// Gets the rate in effect at HEIGHT given that CURRENT is the
+    /// most recently set rate.
+    pub(crate) fn rate_at(
+        &self,
+        storage: &dyn Storage,
+        current: Rate,
+        height: u64,
+    ) -> StdResult<Rate> {
+        Ok(self
+            .first_change(storage, height, None)?
+            .map_or(current, |(_, change)| change.previous))
+    }
+
+    /// Translates INFO, recorded under whatever rate was in effect
+    /// when it was last updated, to RATE, the rate in effect at
+    /// HEIGHT.
+    pub(crate) fn effective(
+        &self,
+        storage: &dyn Storage,
+        rate: Rate,
+        info: Option<RateInfo>,
+        height: u64,
+    ) -> StdResult<Effective> {
+        let info = match info {
+            Some(info) => info,
+            None => return Ok(Effective::Active(rate, RateInfo::default())),
+        };
+        let (effective_height, change) =
+            match self.first_change(storage, info.last_updated_height, Some(height))? {
+                Some(change) => change,
+                None => return Ok(Effective::Active(rate, info)),
+            };
+
+        // Rates only change between blocks, so a window under a
+        // `PerBlock` rate is always over by the time a change takes
+        // effect. Only `Blocks` windows can span the change.
+        let window_end = match change.previous {
+            Rate::Blocks(blocks) => info.blocks_window_end(blocks),
+            Rate::PerBlock(_) => info.last_updated_height,
+        };
+        if window_end <= effective_height {
+            return Ok(Effective::Active(rate, RateInfo::default()));
+        }
+        Ok(match change.transition {
+            RateTransition::Reset => Effective::Active(rate, RateInfo::default()),
+            RateTransition::NextWindow if window_end > height => {
+                Effective::BlockedUntil(window_end)
+            }
+            RateTransition::NextWindow => Effective::Active(rate, RateInfo::default()),
+            RateTransition::Proportional => {
+                // window_end > effective_height so previous is a
+                // non-zero `Blocks` rate.
+                let old_blocks = window_end - info.last_updated_height;
+                let remaining = window_end - effective_height;
+                let carry = |new: u64| {
+                    (remaining as u128 * new as u128).div_ceil(old_blocks as u128) as u64
+                };
+                match rate {
+                    Rate::Blocks(blocks) => {
+                        let until = effective_height + carry(blocks);
+                        if until > height {
+                            Effective::BlockedUntil(until)
+                        } else {
+                            Effective::Active(rate, RateInfo::default())
+                        }
+                    }
+                    Rate::PerBlock(limit) if height == effective_height => Effective::Active(
+                        rate,
+                        RateInfo {
+                            last_updated_height: effective_height,
+                            this_block: carry(limit),
+                            credit: 0,
+                        },
+                    ),
+                    Rate::PerBlock(_) => Effective::Active(rate, RateInfo::default()),
+                }
+            }
+        })
+    }
+
+    fn record(
+        &self,
+        storage: &mut dyn Storage,
+        effective_height: u64,
+        change: &RateChange,
+    ) -> StdResult<()> {
+        self.changes.save(storage, effective_height, change)
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables `update_rate`, storing the history of rate changes
+    /// under CHANGES_KEY.
+    pub const fn with_rate_updates(mut self, changes_key: &'a str) -> Self {
+        self.rate_changes = Some(RateChanges::new(changes_key));
+        self
+    }
+
+    /// Changes the rate limit to RATE. The new rate takes effect from
+    /// the next block, so every limit in the current block is checked
+    /// against the same rate. TRANSITION determines how usage
+    /// recorded under the previous rate counts against the new
+    /// one. Keys are translated lazily the next time they are
+    /// limited or queried.
+    ///
+    /// If the rate changes more than once before a key is next
+    /// limited, its usage is translated directly from the rate it was
+    /// recorded under to the latest rate, using the transition of the
+    /// first of those changes.
+    ///
+    /// Errors if rate updates are not enabled.
+    pub fn update_rate(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        rate: Rate,
+        transition: RateTransition,
+    ) -> StdResult<()> {
+        let changes = self
+            .rate_changes
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not support rate updates"))?;
+        let height = env.block.height;
+        let previous = changes.rate_at(storage, self.rate_limit.load(storage)?, height)?;
+        changes.record(
+            storage,
+            height + 1,
+            &RateChange {
+                previous,
+                transition,
+            },
+        )?;
+        // Recorded against this height so that queries at it return
+        // the rate that was in effect during it.
+        self.save_rate_limit(storage, &rate, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    use crate::RateLimitError;
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r").with_rate_updates("rc");
+
+    fn setup(rate: Rate) -> (MockStorage, Env) {
+        let mut storage = MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(&mut storage, &rate).unwrap();
+        (storage, env)
+    }
+
+    fn limited(key: &str, blocks_remaining: u64) -> RateLimitError {
+        RateLimitError::Limited {
+            key: key.to_string(),
+            blocks_remaining,
+        }
+    }
+
+    #[test]
+    fn test_update_takes_effect_next_block() {
+        let (mut storage, mut env) = setup(Rate::PerBlock(1));
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        LIMITER
+            .update_rate(&mut storage, &env, Rate::PerBlock(3), RateTransition::Reset)
+            .unwrap();
+        assert_eq!(
+            LIMITER.limit(&mut storage, &env, "a").unwrap_err(),
+            limited("a", 1)
+        );
+        assert_eq!(
+            LIMITER
+                .query_current_rate(&storage, env.block.height)
+                .unwrap(),
+            Rate::PerBlock(1)
+        );
+        assert_eq!(
+            LIMITER
+                .query_current_rate(&storage, env.block.height + 1)
+                .unwrap(),
+            Rate::PerBlock(3)
+        );
+
+        env.block.height += 1;
+        for _ in 0..3 {
+            LIMITER.limit(&mut storage, &env, "a").unwrap();
+        }
+        LIMITER.limit(&mut storage, &env, "a").unwrap_err();
+    }
+
+    #[test]
+    fn test_reset() {
+        let (mut storage, mut env) = setup(Rate::Blocks(10));
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        LIMITER
+            .update_rate(&mut storage, &env, Rate::Blocks(20), RateTransition::Reset)
+            .unwrap();
+        env.block.height += 1;
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        assert_eq!(
+            LIMITER.limit(&mut storage, &env, "a").unwrap_err(),
+            limited("a", 20)
+        );
+    }
+
+    #[test]
+    fn test_next_window() {
+        let (mut storage, mut env) = setup(Rate::Blocks(10));
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        LIMITER
+            .update_rate(
+                &mut storage,
+                &env,
+                Rate::PerBlock(5),
+                RateTransition::NextWindow,
+            )
+            .unwrap();
+        env.block.height += 4;
+        assert_eq!(
+            LIMITER.limit(&mut storage, &env, "a").unwrap_err(),
+            limited("a", 6)
+        );
+        env.block.height += 6;
+        for _ in 0..5 {
+            LIMITER.limit(&mut storage, &env, "a").unwrap();
+        }
+        LIMITER.limit(&mut storage, &env, "a").unwrap_err();
+    }
+
+    #[test]
+    fn test_proportional() {
+        let (mut storage, mut env) = setup(Rate::Blocks(10));
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        env.block.height = 104;
+        LIMITER
+            .update_rate(
+                &mut storage,
+                &env,
+                Rate::Blocks(4),
+                RateTransition::Proportional,
+            )
+            .unwrap();
+        // Takes effect at 105, when half of the old window remains.
+        env.block.height = 105;
+        assert_eq!(
+            LIMITER.limit(&mut storage, &env, "a").unwrap_err(),
+            limited("a", 2)
+        );
+        env.block.height = 107;
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+    }
+
+    #[test]
+    fn test_proportional_per_block() {
+        let (mut storage, mut env) = setup(Rate::Blocks(10));
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        env.block.height = 104;
+        LIMITER
+            .update_rate(
+                &mut storage,
+                &env,
+                Rate::PerBlock(4),
+                RateTransition::Proportional,
+            )
+            .unwrap();
+        env.block.height = 105;
+        // Half of the window remains, so half of the first block's
+        // quota is used.
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        LIMITER.limit(&mut storage, &env, "a").unwrap();
+        LIMITER.limit(&mut storage, &env, "a").unwrap_err();
+        env.block.height = 106;
+        for _ in 0..4 {
+            LIMITER.limit(&mut storage, &env, "a").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_update_history() {
+        let limiter =
+            RateLimiter::new_snapshotted("rl", "r", "c", "rlc", "rc").with_rate_updates("changes");
+        let storage = &mut MockStorage::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        limiter.init(storage, &Rate::PerBlock(1)).unwrap();
+        limiter
+            .update_rate(storage, &env, Rate::PerBlock(2), RateTransition::Reset)
+            .unwrap();
+        env.block.height = 110;
+        limiter
+            .update_rate(storage, &env, Rate::PerBlock(3), RateTransition::Reset)
+            .unwrap();
+
+        assert_eq!(
+            limiter.query_limit_at_height(storage, 100).unwrap(),
+            Rate::PerBlock(1)
+        );
+        assert_eq!(
+            limiter.query_limit_at_height(storage, 101).unwrap(),
+            Rate::PerBlock(2)
+        );
+        assert_eq!(
+            limiter.query_limit_at_height(storage, 110).unwrap(),
+            Rate::PerBlock(2)
+        );
+        assert_eq!(
+            limiter.query_limit_at_height(storage, 111).unwrap(),
+            Rate::PerBlock(3)
+        );
+    }
+
+    #[test]
+    fn test_updates_disabled() {
+        let limiter = RateLimiter::new("rl", "r");
+        let storage = &mut MockStorage::default();
+        limiter.init(storage, &Rate::PerBlock(1)).unwrap();
+        limiter
+            .update_rate(
+                storage,
+                &mock_env(),
+                Rate::PerBlock(2),
+                RateTransition::Reset,
+            )
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,579 @@
+use cosmwasm_schema::cw_serde;
+use cosmwasm_std::{
+    to_vec, Addr, Api, Binary, Empty, Env, Event, Order, StdError, StdResult, Storage,
+};
+use cw_storage_plus::{Item, Map};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{RateLimitError, RateLimiter};
+
+/// The public key of the off-chain authority that signs vouchers.
+#[cw_serde]
+pub enum VoucherAuthority {
+    /// A compressed (33 byte) or uncompressed (65 byte) secp256k1
+    /// public key. Signatures are over the SHA-256 hash of the
+    /// voucher's sign bytes.
+    Secp256k1 { pubkey: Binary },
+    /// A 32 byte ed25519 public key. Signatures are over the
+    /// voucher's sign bytes.
+    Ed25519 { pubkey: Binary },
+}
+
+/// A permit for a key to exceed its limit.
+#[cw_serde]
+pub struct Voucher {
+    pub key: String,
+    /// Units the key may use beyond its limit.
+    pub units: u64,
+    /// The voucher, and units granted by it, may only be used below
+    /// this height.
+    pub expires_at_height: u64,
+    /// Identifies the voucher so that it may only be redeemed once.
+    pub nonce: u64,
+}
+
+#[cw_serde]
+pub struct SignedVoucher {
+    pub voucher: Voucher,
+    pub signature: Binary,
+}
+
+/// Units granted to a key by a voucher that have not yet been used.
+#[cw_serde]
+pub struct Bypass {
+    /// The nonce of the voucher that granted these units.
+    pub nonce: u64,
+    pub units: u64,
+    pub expires_at_height: u64,
+}
+
+// What the authority signs. Binding vouchers to a chain and contract
+// stops them being replayed elsewhere.
+#[derive(Serialize)]
+struct SignDoc<'a> {
+    chain_id: &'a str,
+    contract: &'a Addr,
+    voucher: &'a Voucher,
+}
+
+/// The bytes an authority signs to issue VOUCHER for the contract at
+/// CONTRACT on CHAIN_ID: the JSON encoding of
+/// `{"chain_id":..,"contract":..,"voucher":{..}}`.
+pub fn voucher_sign_bytes(
+    chain_id: &str,
+    contract: &Addr,
+    voucher: &Voucher,
+) -> StdResult<Vec<u8>> {
+    to_vec(&SignDoc {
+        chain_id,
+        contract,
+        voucher,
+    })
+}
+
+pub(crate) struct Vouchers<'a, 'b> {
+    authority: Item<'a, VoucherAuthority>,
+    nonces: Map<'a, u64, Empty>,
+    bypasses: Map<'a, (&'b str, u64), Bypass>,
+}
+
+// Uses COST units from BYPASSES, which must be sorted by expiry,
+// spending those that expire soonest first. Returns false, leaving
+// BYPASSES as-is, if fewer than COST units are usable at HEIGHT.
+pub(crate) fn spend(bypasses: &mut [Bypass], height: u64, cost: u64) -> bool {
+    let usable = |b: &&mut Bypass| b.expires_at_height > height;
+    let available = bypasses
+        .iter_mut()
+        .filter(usable)
+        .fold(0u64, |n, b| n.saturating_add(b.units));
+    if available < cost {
+        return false;
+    }
+    let mut cost = cost;
+    for bypass in bypasses.iter_mut().filter(usable) {
+        let used = bypass.units.min(cost);
+        bypass.units -= used;
+        cost -= used;
+    }
+    true
+}
+
+impl<'a, 'b> Vouchers<'a, 'b> {
+    pub(crate) const fn new(
+        authority_key: &'a str,
+        nonces_key: &'a str,
+        bypasses_key: &'a str,
+    ) -> Self {
+        Self {
+            authority: Item::new(authority_key),
+            nonces: Map::new(nonces_key),
+            bypasses: Map::new(bypasses_key),
+        }
+    }
+
+    /// KEY's bypasses, including expired ones, in the order they
+    /// are spent: soonest to expire first.
+    pub(crate) fn load(&self, storage: &dyn Storage, key: &str) -> StdResult<Vec<Bypass>> {
+        let mut bypasses = self
+            .bypasses
+            .prefix(key)
+            .range(storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(_, bypass)| bypass))
+            .collect::<StdResult<Vec<_>>>()?;
+        bypasses.sort_by_key(|b| (b.expires_at_height, b.nonce));
+        Ok(bypasses)
+    }
+
+    /// Saves BYPASSES, as loaded by `load` and then spent from, for
+    /// KEY. Removes those that are used up or expired at HEIGHT.
+    pub(crate) fn save(
+        &self,
+        storage: &mut dyn Storage,
+        height: u64,
+        key: &str,
+        bypasses: &[Bypass],
+    ) -> StdResult<()> {
+        for bypass in bypasses {
+            if bypass.units == 0 || bypass.expires_at_height <= height {
+                self.bypasses.remove(storage, (key, bypass.nonce));
+            } else {
+                self.bypasses.save(storage, (key, bypass.nonce), bypass)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> RateLimiter<'a, 'b> {
+    /// Enables bypass vouchers, stored under AUTHORITY_KEY,
+    /// NONCES_KEY and BYPASSES_KEY. Vouchers may only be redeemed
+    /// once an authority is set with `set_voucher_authority`.
+    pub const fn with_vouchers(
+        mut self,
+        authority_key: &'a str,
+        nonces_key: &'a str,
+        bypasses_key: &'a str,
+    ) -> Self {
+        self.vouchers = Some(Vouchers::new(authority_key, nonces_key, bypasses_key));
+        self
+    }
+
+    /// Sets the key vouchers must be signed with, or stops accepting
+    /// vouchers if AUTHORITY is `None`. Units granted by vouchers
+    /// that have already been redeemed may still be used. Errors if
+    /// vouchers are not enabled or the public key has the wrong
+    /// length.
+    pub fn set_voucher_authority(
+        &self,
+        storage: &mut dyn Storage,
+        authority: Option<&VoucherAuthority>,
+    ) -> StdResult<()> {
+        let vouchers = self.vouchers()?;
+        match authority {
+            Some(VoucherAuthority::Secp256k1 { pubkey }) if ![33, 65].contains(&pubkey.len()) => {
+                Err(StdError::generic_err(
+                    "secp256k1 public key must be 33 or 65 bytes",
+                ))
+            }
+            Some(VoucherAuthority::Ed25519 { pubkey }) if pubkey.len() != 32 => {
+                Err(StdError::generic_err("ed25519 public key must be 32 bytes"))
+            }
+            Some(authority) => vouchers.authority.save(storage, authority),
+            None => {
+                vouchers.authority.remove(storage);
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks that SIGNED was signed by the voucher authority for
+    /// this contract and has not been used, then grants its units to
+    /// its key. Calls to `limit` for the key that would otherwise be
+    /// rejected use these units instead of failing, until they run
+    /// out or expire. Units from several vouchers add up, but each
+    /// voucher's units expire with it, and those expiring soonest are
+    /// used first.
+    ///
+    /// Returns a `rate_limit_voucher` event with `key`, `units` and
+    /// `nonce` attributes. Errors if vouchers are not enabled, no
+    /// authority is set, or the voucher is invalid.
+    pub fn redeem_voucher(
+        &self,
+        storage: &mut dyn Storage,
+        api: &dyn Api,
+        env: &Env,
+        signed: &SignedVoucher,
+    ) -> Result<Event, RateLimitError> {
+        let vouchers = self.vouchers()?;
+        let authority = vouchers
+            .authority
+            .may_load(storage)?
+            .ok_or_else(|| StdError::generic_err("no voucher authority is set"))?;
+        let voucher = &signed.voucher;
+        let sign_bytes = voucher_sign_bytes(&env.block.chain_id, &env.contract.address, voucher)?;
+        let valid = match authority {
+            VoucherAuthority::Secp256k1 { pubkey } => {
+                let hash = Sha256::digest(&sign_bytes);
+                api.secp256k1_verify(&hash, &signed.signature, &pubkey)
+            }
+            VoucherAuthority::Ed25519 { pubkey } => {
+                api.ed25519_verify(&sign_bytes, &signed.signature, &pubkey)
+            }
+        }
+        .map_err(StdError::from)?;
+        if !valid {
+            return Err(RateLimitError::InvalidVoucherSignature {});
+        }
+        if voucher.expires_at_height <= env.block.height {
+            return Err(RateLimitError::VoucherExpired {
+                nonce: voucher.nonce,
+            });
+        }
+        if vouchers.nonces.has(storage, voucher.nonce) {
+            return Err(RateLimitError::VoucherUsed {
+                nonce: voucher.nonce,
+            });
+        }
+        vouchers.nonces.save(storage, voucher.nonce, &Empty {})?;
+
+        if voucher.units > 0 {
+            vouchers.bypasses.save(
+                storage,
+                (&voucher.key, voucher.nonce),
+                &Bypass {
+                    nonce: voucher.nonce,
+                    units: voucher.units,
+                    expires_at_height: voucher.expires_at_height,
+                },
+            )?;
+        }
+        Ok(Event::new("rate_limit_voucher")
+            .add_attribute("key", &voucher.key)
+            .add_attribute("units", voucher.units.to_string())
+            .add_attribute("nonce", voucher.nonce.to_string()))
+    }
+
+    pub fn query_voucher_authority(
+        &self,
+        storage: &dyn Storage,
+    ) -> StdResult<Option<VoucherAuthority>> {
+        self.vouchers()?.authority.may_load(storage)
+    }
+
+    /// Lists the units KEY may use beyond its limit at HEIGHT, in the
+    /// order they will be used. Errors if vouchers are not enabled.
+    pub fn query_bypass(
+        &self,
+        storage: &dyn Storage,
+        height: u64,
+        key: &str,
+    ) -> StdResult<Vec<Bypass>> {
+        Ok(self
+            .vouchers()?
+            .load(storage, key)?
+            .into_iter()
+            .filter(|bypass| bypass.units > 0 && bypass.expires_at_height > height)
+            .collect())
+    }
+
+    /// Returns true if the voucher with NONCE has been redeemed.
+    /// Errors if vouchers are not enabled.
+    pub fn query_voucher_used(&self, storage: &dyn Storage, nonce: u64) -> StdResult<bool> {
+        Ok(self.vouchers()?.nonces.has(storage, nonce))
+    }
+
+    fn vouchers(&self) -> StdResult<&Vouchers<'a, 'b>> {
+        self.vouchers
+            .as_ref()
+            .ok_or_else(|| StdError::generic_err("rate limiter does not accept vouchers"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+    use ed25519_zebra::{SigningKey as Ed25519Key, VerificationKey};
+    use k256::ecdsa::{signature::DigestSigner, Signature, SigningKey as Secp256k1Key};
+
+    use crate::Rate;
+
+    use super::*;
+
+    const LIMITER: RateLimiter = RateLimiter::new("rl", "r").with_vouchers("va", "vn", "vb");
+
+    fn secp256k1() -> (Secp256k1Key, VoucherAuthority) {
+        let key = Secp256k1Key::from_bytes(&[7; 32]).unwrap();
+        let pubkey = key.verifying_key().to_bytes().to_vec();
+        (
+            key,
+            VoucherAuthority::Secp256k1 {
+                pubkey: Binary(pubkey),
+            },
+        )
+    }
+
+    fn sign_secp256k1(key: &Secp256k1Key, env: &Env, voucher: Voucher) -> SignedVoucher {
+        let bytes =
+            voucher_sign_bytes(&env.block.chain_id, &env.contract.address, &voucher).unwrap();
+        let signature: Signature = key.sign_digest(Sha256::new().chain_update(bytes));
+        SignedVoucher {
+            voucher,
+            signature: Binary(signature.as_ref().to_vec()),
+        }
+    }
+
+    #[test]
+    fn test_voucher() {
+        let storage = &mut MockStorage::default();
+        let api = MockApi::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::PerBlock(1)).unwrap();
+        let (key, authority) = secp256k1();
+        LIMITER
+            .set_voucher_authority(storage, Some(&authority))
+            .unwrap();
+
+        let voucher = Voucher {
+            key: "a".to_string(),
+            units: 2,
+            expires_at_height: 102,
+            nonce: 1,
+        };
+        let signed = sign_secp256k1(&key, &env, voucher.clone());
+        assert_eq!(
+            LIMITER
+                .redeem_voucher(storage, &api, &env, &signed)
+                .unwrap(),
+            Event::new("rate_limit_voucher")
+                .add_attribute("key", "a")
+                .add_attribute("units", "2")
+                .add_attribute("nonce", "1")
+        );
+        assert_eq!(
+            LIMITER
+                .redeem_voucher(storage, &api, &env, &signed)
+                .unwrap_err(),
+            RateLimitError::VoucherUsed { nonce: 1 }
+        );
+        assert!(LIMITER.query_voucher_used(storage, 1).unwrap());
+
+        // One unit from the limit, two from the voucher.
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap();
+        assert_eq!(
+            LIMITER.query_bypass(storage, 100, "a").unwrap(),
+            vec![Bypass {
+                nonce: 1,
+                units: 1,
+                expires_at_height: 102
+            }]
+        );
+        LIMITER.limit_many(storage, &env, &[("a", 1)]).unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        assert_eq!(LIMITER.query_bypass(storage, 100, "a").unwrap(), vec![]);
+
+        // Unused units expire with the voucher.
+        let signed = sign_secp256k1(
+            &key,
+            &env,
+            Voucher {
+                nonce: 2,
+                ..voucher.clone()
+            },
+        );
+        LIMITER
+            .redeem_voucher(storage, &api, &env, &signed)
+            .unwrap();
+        env.block.height = 102;
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        assert_eq!(
+            LIMITER
+                .redeem_voucher(storage, &api, &env, &signed)
+                .unwrap_err(),
+            RateLimitError::VoucherExpired { nonce: 2 }
+        );
+
+        // Vouchers for another key, or contract, are rejected.
+        let mut signed = sign_secp256k1(
+            &key,
+            &env,
+            Voucher {
+                nonce: 3,
+                ..voucher.clone()
+            },
+        );
+        signed.voucher.key = "b".to_string();
+        assert_eq!(
+            LIMITER
+                .redeem_voucher(storage, &api, &env, &signed)
+                .unwrap_err(),
+            RateLimitError::InvalidVoucherSignature {}
+        );
+        let signed = sign_secp256k1(
+            &key,
+            &env,
+            Voucher {
+                nonce: 3,
+                ..voucher
+            },
+        );
+        env.contract.address = Addr::unchecked("other");
+        assert_eq!(
+            LIMITER
+                .redeem_voucher(storage, &api, &env, &signed)
+                .unwrap_err(),
+            RateLimitError::InvalidVoucherSignature {}
+        );
+    }
+
+    #[test]
+    fn test_voucher_expiries() {
+        let storage = &mut MockStorage::default();
+        let api = MockApi::default();
+        let mut env = mock_env();
+        env.block.height = 100;
+        LIMITER.init(storage, &Rate::PerBlock(0)).unwrap();
+        let (key, authority) = secp256k1();
+        LIMITER
+            .set_voucher_authority(storage, Some(&authority))
+            .unwrap();
+
+        for (nonce, units, expires_at_height) in [(1, 50, 102), (2, 1, 100_000), (3, 2, 101)] {
+            let signed = sign_secp256k1(
+                &key,
+                &env,
+                Voucher {
+                    key: "a".to_string(),
+                    units,
+                    expires_at_height,
+                    nonce,
+                },
+            );
+            LIMITER
+                .redeem_voucher(storage, &api, &env, &signed)
+                .unwrap();
+        }
+        assert_eq!(
+            LIMITER
+                .query_bypass(storage, 100, "a")
+                .unwrap()
+                .iter()
+                .map(|b| b.nonce)
+                .collect::<Vec<_>>(),
+            vec![3, 1, 2]
+        );
+
+        // Units expiring soonest are used first, across vouchers.
+        LIMITER.limit_many(storage, &env, &[("a", 3)]).unwrap();
+        assert_eq!(
+            LIMITER.query_bypass(storage, 100, "a").unwrap(),
+            vec![
+                Bypass {
+                    nonce: 1,
+                    units: 49,
+                    expires_at_height: 102
+                },
+                Bypass {
+                    nonce: 2,
+                    units: 1,
+                    expires_at_height: 100_000
+                }
+            ]
+        );
+
+        // Nothing is spent if another key can not be limited.
+        LIMITER
+            .limit_many(storage, &env, &[("a", 1), ("b", 1)])
+            .unwrap_err();
+        assert_eq!(LIMITER.query_bypass(storage, 100, "a").unwrap().len(), 2);
+        assert_eq!(
+            LIMITER.query_bypass(storage, 100, "a").unwrap()[0].units,
+            49
+        );
+
+        // Each voucher's units expire with it, regardless of later
+        // vouchers for the same key.
+        env.block.height = 50_000;
+        LIMITER.limit_many(storage, &env, &[("a", 2)]).unwrap_err();
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+        assert_eq!(LIMITER.query_bypass(storage, 50_000, "a").unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_ed25519_voucher() {
+        let storage = &mut MockStorage::default();
+        let api = MockApi::default();
+        let env = mock_env();
+        LIMITER.init(storage, &Rate::PerBlock(0)).unwrap();
+        let key = Ed25519Key::from([9; 32]);
+        let pubkey: [u8; 32] = VerificationKey::from(&key).into();
+        LIMITER
+            .set_voucher_authority(
+                storage,
+                Some(&VoucherAuthority::Ed25519 {
+                    pubkey: Binary(pubkey.to_vec()),
+                }),
+            )
+            .unwrap();
+
+        let voucher = Voucher {
+            key: "a".to_string(),
+            units: 1,
+            expires_at_height: env.block.height + 1,
+            nonce: 1,
+        };
+        let bytes =
+            voucher_sign_bytes(&env.block.chain_id, &env.contract.address, &voucher).unwrap();
+        let signature: [u8; 64] = key.sign(&bytes).into();
+        LIMITER
+            .redeem_voucher(
+                storage,
+                &api,
+                &env,
+                &SignedVoucher {
+                    voucher,
+                    signature: Binary(signature.to_vec()),
+                },
+            )
+            .unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap();
+        LIMITER.limit(storage, &env, "a").unwrap_err();
+    }
+
+    #[test]
+    fn test_voucher_errors() {
+        let storage = &mut MockStorage::default();
+        let api = MockApi::default();
+        let env = mock_env();
+        let (key, authority) = secp256k1();
+        let signed = sign_secp256k1(
+            &key,
+            &env,
+            Voucher {
+                key: "a".to_string(),
+                units: 1,
+                expires_at_height: env.block.height + 1,
+                nonce: 1,
+            },
+        );
+        // No authority.
+        LIMITER
+            .redeem_voucher(storage, &api, &env, &signed)
+            .unwrap_err();
+        LIMITER
+            .set_voucher_authority(
+                storage,
+                Some(&VoucherAuthority::Ed25519 {
+                    pubkey: Binary(vec![1; 33]),
+                }),
+            )
+            .unwrap_err();
+        RateLimiter::new("rl", "r")
+            .set_voucher_authority(storage, Some(&authority))
+            .unwrap_err();
+    }
+}